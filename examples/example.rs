@@ -2,24 +2,10 @@ extern crate netlink_rust;
 
 use netlink_rust as netlink;
 
+use crate::netlink::decode;
 use crate::netlink::generic;
 use crate::netlink::route;
-use crate::netlink::route::{AddressFamilyAttribute, InterfaceInformationMessage};
-use crate::netlink::{Message, Protocol, Socket};
-
-fn handle_message(message: &Message) {
-    if message.header.identifier == route::FamilyId::NewLink {
-        let (_, msg) = InterfaceInformationMessage::unpack(&message.data).unwrap();
-        for attr in msg.attributes {
-            if attr.identifier == AddressFamilyAttribute::InterfaceName {
-                let name = attr.as_string().unwrap();
-                println!("{}", name);
-            }
-        }
-    } else {
-        println!("Header: {}", message.header);
-    }
-}
+use crate::netlink::{Protocol, Socket};
 
 fn get_network_interfaces(socket: &mut Socket) {
     {
@@ -28,7 +14,7 @@ fn get_network_interfaces(socket: &mut Socket) {
     }
     let messages = socket.receive_messages().unwrap();
     for message in messages {
-        handle_message(&message);
+        println!("{}", decode::decode(Protocol::Route, &message));
     }
 }
 
@@ -0,0 +1,27 @@
+extern crate netlink_rust;
+extern crate tokio;
+
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use futures_core::Stream;
+use netlink_rust as netlink;
+
+use crate::netlink::route::{self, AddressFamilyAttribute, InterfaceInformationMessage};
+use crate::netlink::{AsyncSocket, Protocol};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let socket = AsyncSocket::new(Protocol::Route).unwrap();
+    let tx_msg = route::Message::new(route::FamilyId::GetLink);
+    let mut links = socket.dump(tx_msg).await.unwrap();
+    while let Some(message) = poll_fn(|cx| Pin::new(&mut links).poll_next(cx)).await {
+        let message = message.unwrap();
+        let (_, msg) = InterfaceInformationMessage::unpack(&message.data).unwrap();
+        for attr in msg.attributes {
+            if attr.attribute_type() == AddressFamilyAttribute::InterfaceName {
+                println!("{}", attr.as_string().unwrap());
+            }
+        }
+    }
+}
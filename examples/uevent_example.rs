@@ -29,6 +29,11 @@ fn receive_messages(socket: &mut Socket) {
     loop {
         let result = socket.receive();
         match result {
+            Err(netlink::Error::Overrun) => {
+                // Events were dropped, a real monitor would re-read the state
+                // it tracks from sysfs here
+                println!("Events lost, the receive buffer overran");
+            }
             Err(e) => {
                 println!("Failed to receive message(s), {:?}", e);
                 break;
@@ -60,7 +65,9 @@ fn main() {
 
     let timeout = Duration::from_millis(500);
     // When listening to uevents we need to provide the multicast group 1
-    let mut socket = Socket::new_multicast(Protocol::KObjectUevent, 1).unwrap();
+    let mut socket = Socket::new_multicast(Protocol::KObjectUevent, &[1]).unwrap();
+    // Other processes can send to the uevent group, only trust the kernel
+    socket.set_kernel_only(true);
     // register socket in event loop
     poll.registry()
         .register(&mut SourceFd(&socket.as_raw_fd()), NETLINK, Interest::READABLE).unwrap();
@@ -0,0 +1,476 @@
+//! Derive macros for netlink-rust
+//!
+//! Use through the `derive` feature of the netlink-rust crate, which
+//! re-exports `NativePack` and `NativeUnpack`.
+//!
+//! Fields without an `nla` attribute form the fixed header of the message
+//! and are laid out as in a C struct. Fields with an `nla` attribute are
+//! netlink attributes following the header, 4 byte aligned.
+//!
+//! ```text
+//! #[derive(NativePack, NativeUnpack)]
+//! struct Link {
+//!     family: u8,
+//!     kind: u16,
+//!     index: i32,
+//!     #[nla(id = 3, string)]
+//!     name: String,
+//!     #[nla(id = 4)]
+//!     mtu: Option<u32>,
+//! }
+//! ```
+//!
+//! The `nla` attribute takes the attribute type as `id`, either an integer
+//! or an identifier enumeration value, and optionally the kind of the
+//! attribute.
+//!
+//! * No kind, the field type implements `ToAttribute` and `FromAttribute`.
+//! * `string`, a nul terminated string.
+//! * `flag`, a `bool` which is true if the attribute is present.
+//! * `nested`, the field type implements `NativePack` and `NativeUnpack`,
+//!   typically derived, and is packed as nested attributes.
+//!
+//! Attributes wrapped in `Option` may be missing, unpacking fails with
+//! `Error::MissingAttribute` if any other attribute is missing. Unknown
+//! attributes are ignored.
+
+extern crate proc_macro;
+
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, ExprLit, Fields, GenericArgument, Ident, Lit,
+    PathArguments, Type,
+};
+
+/// Derive `NativePack` for a struct, see the crate documentation
+#[proc_macro_derive(NativePack, attributes(nla))]
+pub fn derive_native_pack(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match parse_struct(&input) {
+        Ok(fields) => pack_impl(&input, &fields).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Derive `NativeUnpack` for a struct, see the crate documentation
+#[proc_macro_derive(NativeUnpack, attributes(nla))]
+pub fn derive_native_unpack(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match parse_struct(&input) {
+        Ok(fields) => unpack_impl(&input, &fields).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    Value,
+    String,
+    Flag,
+    Nested,
+}
+
+struct HeaderField {
+    ident: Ident,
+    ty: Type,
+}
+
+struct AttributeField {
+    ident: Ident,
+    /// Field type, without `Option`
+    ty: Type,
+    optional: bool,
+    identifier: TokenStream,
+    kind: Kind,
+}
+
+struct StructFields {
+    header: Vec<HeaderField>,
+    attributes: Vec<AttributeField>,
+}
+
+fn parse_struct(input: &DeriveInput) -> syn::Result<StructFields> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "only structs with named fields are supported",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "only structs are supported",
+            ))
+        }
+    };
+    let mut header = vec![];
+    let mut attributes = vec![];
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let options = field.attrs.iter().find(|attr| attr.path().is_ident("nla"));
+        match options {
+            None => header.push(HeaderField {
+                ident,
+                ty: field.ty.clone(),
+            }),
+            Some(options) => {
+                let (identifier, kind) = parse_options(options)?;
+                let (ty, optional) = match option_type(&field.ty) {
+                    Some(ty) => (ty.clone(), true),
+                    None => (field.ty.clone(), false),
+                };
+                if kind == Kind::Flag && optional {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "a flag is a bool, not an option",
+                    ));
+                }
+                attributes.push(AttributeField {
+                    ident,
+                    ty,
+                    optional,
+                    identifier,
+                    kind,
+                });
+            }
+        }
+    }
+    Ok(StructFields { header, attributes })
+}
+
+/// Parse `#[nla(id = ..., kind)]`, returning the attribute type as a u16
+/// expression
+fn parse_options(attr: &syn::Attribute) -> syn::Result<(TokenStream, Kind)> {
+    let mut identifier = None;
+    let mut kind = Kind::Value;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("id") {
+            let value: Expr = meta.value()?.parse()?;
+            identifier = Some(match value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(ref lit),
+                    ..
+                }) => {
+                    let literal = Literal::u16_suffixed(lit.base10_parse::<u16>()?);
+                    quote!(#literal)
+                }
+                value => quote!(u16::from(#value)),
+            });
+        } else if meta.path.is_ident("string") {
+            kind = Kind::String;
+        } else if meta.path.is_ident("flag") {
+            kind = Kind::Flag;
+        } else if meta.path.is_ident("nested") {
+            kind = Kind::Nested;
+        } else {
+            return Err(meta.error("expected `id`, `string`, `flag` or `nested`"));
+        }
+        Ok(())
+    })?;
+    match identifier {
+        Some(identifier) => Ok((identifier, kind)),
+        None => Err(syn::Error::new_spanned(attr, "missing attribute `id`")),
+    }
+}
+
+/// Get `T` of `Option<T>`
+fn option_type(ty: &Type) -> Option<&Type> {
+    let path = match *ty {
+        Type::Path(ref path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref arguments) if arguments.args.len() == 1 => {
+            match arguments.args[0] {
+                GenericArgument::Type(ref ty) => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Code collecting the attribute fields of `self` into `attributes`
+fn collect_attributes(fields: &StructFields) -> TokenStream {
+    let pushes = fields.attributes.iter().map(|field| {
+        let ident = &field.ident;
+        let identifier = &field.identifier;
+        let attribute = match field.kind {
+            Kind::Value => quote! {
+                ::netlink_rust::core::ToAttribute::to_attribute(value, #identifier)
+            },
+            Kind::String => quote! {
+                ::netlink_rust::Attribute::new_string_with_nul(#identifier, value)
+            },
+            Kind::Nested => quote! {{
+                let mut data = vec![0u8; ::netlink_rust::NativePack::pack_size(value)];
+                ::netlink_rust::NativePack::pack_unchecked(value, &mut data);
+                ::netlink_rust::Attribute::new_bytes(
+                    #identifier | ::netlink_rust::core::NLA_F_NESTED,
+                    &data,
+                )
+            }},
+            Kind::Flag => {
+                return quote! {
+                    if self.#ident {
+                        attributes.push(::netlink_rust::Attribute::new_bytes(#identifier, &[]));
+                    }
+                };
+            }
+        };
+        if field.optional {
+            quote! {
+                if let Some(ref value) = self.#ident {
+                    attributes.push(#attribute);
+                }
+            }
+        } else {
+            quote! {
+                {
+                    let value = &self.#ident;
+                    attributes.push(#attribute);
+                }
+            }
+        }
+    });
+    quote! {
+        let mut attributes: Vec<::netlink_rust::Attribute> = Vec::new();
+        #(#pushes)*
+    }
+}
+
+fn pack_impl(input: &DeriveInput, fields: &StructFields) -> TokenStream {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let header_sizes = fields.header.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        quote! {
+            size = size.next_multiple_of(::std::mem::align_of::<#ty>());
+            size += ::netlink_rust::NativePack::pack_size(&self.#ident);
+        }
+    });
+    let header_packs = fields.header.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        quote! {
+            offset = offset.next_multiple_of(::std::mem::align_of::<#ty>());
+            ::netlink_rust::NativePack::pack_unchecked(&self.#ident, &mut buffer[offset..]);
+            offset += ::netlink_rust::NativePack::pack_size(&self.#ident);
+        }
+    });
+    let (attribute_size, attribute_pack) = if fields.attributes.is_empty() {
+        (quote!(size), quote!(let _ = offset;))
+    } else {
+        let collect = collect_attributes(fields);
+        (
+            quote! {
+                #collect
+                attributes.iter().fold(size.next_multiple_of(4), |size, attribute| {
+                    size + ::netlink_rust::NativePack::pack_size(attribute).next_multiple_of(4)
+                })
+            },
+            quote! {
+                #collect
+                offset = offset.next_multiple_of(4);
+                for attribute in &attributes {
+                    ::netlink_rust::NativePack::pack_unchecked(attribute, &mut buffer[offset..]);
+                    offset += ::netlink_rust::NativePack::pack_size(attribute).next_multiple_of(4);
+                }
+            },
+        )
+    };
+    quote! {
+        impl #impl_generics ::netlink_rust::NativePack for #name #type_generics #where_clause {
+            fn pack_size(&self) -> usize {
+                #[allow(unused_mut)]
+                let mut size = 0usize;
+                #(#header_sizes)*
+                #attribute_size
+            }
+            fn pack_unchecked(&self, buffer: &mut [u8]) {
+                #[allow(unused_mut)]
+                let mut offset = 0usize;
+                #(#header_packs)*
+                #attribute_pack
+            }
+        }
+    }
+}
+
+/// Name of the local variable holding the field, not to clash with the
+/// variables of the generated code
+fn local_ident(ident: &Ident) -> Ident {
+    format_ident!("field_{}", ident)
+}
+
+fn unpack_impl(input: &DeriveInput, fields: &StructFields) -> TokenStream {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let header_unpacks = fields.header.iter().map(|field| {
+        let local = local_ident(&field.ident);
+        let ty = &field.ty;
+        quote! {
+            offset = offset.next_multiple_of(::std::mem::align_of::<#ty>());
+            let (used, #local) = <#ty as ::netlink_rust::NativeUnpack>::unpack_with_size(
+                buffer.get(offset..).unwrap_or(&[]),
+            )?;
+            offset += used;
+        }
+    });
+    let attribute_unpack = if fields.attributes.is_empty() {
+        quote!()
+    } else {
+        let declarations = fields.attributes.iter().map(|field| {
+            let local = local_ident(&field.ident);
+            let ty = &field.ty;
+            if field.kind == Kind::Flag {
+                quote!(let mut #local = false;)
+            } else {
+                quote!(let mut #local: Option<#ty> = None;)
+            }
+        });
+        let identifiers = fields.attributes.iter().map(|field| &field.identifier);
+        let conversions = fields.attributes.iter().map(|field| {
+            let ident = local_ident(&field.ident);
+            let ty = &field.ty;
+            match field.kind {
+                Kind::Value => quote! {
+                    #ident = Some(<#ty as ::netlink_rust::core::FromAttribute>::from_attribute(
+                        attribute,
+                    )?);
+                },
+                Kind::String => quote!(#ident = Some(attribute.as_string()?);),
+                Kind::Nested => quote! {
+                    #ident = Some(<#ty as ::netlink_rust::NativeUnpack>::unpack(
+                        &attribute.as_bytes(),
+                    )?);
+                },
+                Kind::Flag => quote!(#ident = true;),
+            }
+        });
+        let requirements = fields
+            .attributes
+            .iter()
+            .filter(|field| !field.optional && field.kind != Kind::Flag)
+            .map(|field| {
+                let local = local_ident(&field.ident);
+                let identifier = &field.identifier;
+                let name = field.ident.to_string();
+                quote! {
+                    let #local = match #local {
+                        Some(value) => value,
+                        None => {
+                            return Err(::netlink_rust::Error::MissingAttribute {
+                                identifier: #identifier,
+                                name: String::from(#name),
+                            })
+                        }
+                    };
+                }
+            });
+        quote! {
+            offset = offset.next_multiple_of(4).min(buffer.len());
+            let (used, attributes) = ::netlink_rust::Attribute::unpack_all(&buffer[offset..]);
+            offset += used;
+            #(#declarations)*
+            for attribute in &attributes {
+                let identifier = attribute.attribute_type();
+                #(if identifier == #identifiers { #conversions } else)* {}
+            }
+            #(#requirements)*
+        }
+    };
+    let idents = fields
+        .header
+        .iter()
+        .map(|field| &field.ident)
+        .chain(fields.attributes.iter().map(|field| &field.ident));
+    let locals = idents.clone().map(local_ident);
+    quote! {
+        impl #impl_generics ::netlink_rust::NativeUnpack for #name #type_generics #where_clause {
+            fn unpack_with_size(buffer: &[u8]) -> ::netlink_rust::Result<(usize, Self)> {
+                #[allow(unused_mut)]
+                let mut offset = 0usize;
+                #(#header_unpacks)*
+                #attribute_unpack
+                Ok((offset, #name { #(#idents: #locals),* }))
+            }
+            fn unpack_unchecked(buffer: &[u8]) -> Self {
+                Self::unpack_with_size(buffer).unwrap().1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn parse_fields() {
+        let input: DeriveInput = parse_quote! {
+            struct Link {
+                family: u8,
+                #[nla(id = 3, string)]
+                name: String,
+                #[nla(id = AttributeId::Mtu)]
+                mtu: Option<u32>,
+                #[nla(id = 5, flag)]
+                up: bool,
+            }
+        };
+        let fields = parse_struct(&input).unwrap();
+        assert_eq!(fields.header.len(), 1);
+        assert_eq!(fields.attributes.len(), 3);
+        let name = &fields.attributes[0];
+        assert_eq!(name.kind, Kind::String);
+        assert_eq!(name.identifier.to_string(), "3u16");
+        assert!(!name.optional);
+        let mtu = &fields.attributes[1];
+        assert_eq!(mtu.kind, Kind::Value);
+        assert_eq!(
+            mtu.identifier.to_string(),
+            quote!(u16::from(AttributeId::Mtu)).to_string()
+        );
+        assert!(mtu.optional);
+        let ty = &mtu.ty;
+        assert_eq!(quote!(#ty).to_string(), "u32");
+        assert_eq!(fields.attributes[2].kind, Kind::Flag);
+    }
+
+    #[test]
+    fn reject_invalid_options() {
+        let input: DeriveInput = parse_quote! {
+            struct Link {
+                #[nla(string)]
+                name: String,
+            }
+        };
+        assert!(parse_struct(&input).is_err());
+        let input: DeriveInput = parse_quote! {
+            struct Link {
+                #[nla(id = 1, unknown)]
+                name: String,
+            }
+        };
+        assert!(parse_struct(&input).is_err());
+        let input: DeriveInput = parse_quote! {
+            struct Link {
+                #[nla(id = 1, flag)]
+                up: Option<bool>,
+            }
+        };
+        assert!(parse_struct(&input).is_err());
+    }
+}
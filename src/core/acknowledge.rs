@@ -0,0 +1,204 @@
+use std::fmt;
+use std::io;
+
+use crate::core::attribute::{Attribute, NLA_TYPE_MASK};
+use crate::core::message::Header;
+use crate::core::pack::NativeUnpack;
+use crate::core::ConvertFrom;
+
+extended_enum_default!(
+    /// Extended acknowledge attribute identifiers
+    AcknowledgeAttributeId, u16,
+    /// Unused
+    Unused => 0,
+    /// Error message string
+    Message => 1,
+    /// Offset of the invalid attribute in the original message
+    Offset => 2,
+    /// Arbitrary subsystem specific cookie
+    Cookie => 3,
+    /// Policy for the rejected attribute
+    Policy => 4,
+    /// Type of the missing attribute
+    MissingType => 5,
+    /// Offset of the nest where the attribute was missing
+    MissingNest => 6,
+);
+
+extended_enum_default!(
+    /// Attribute policy attribute identifiers
+    PolicyAttributeId, u16,
+    /// Unspecified
+    Unspecified => 0,
+    /// Attribute type
+    Type => 1,
+    /// Minimum signed value
+    MinimumValueSigned => 2,
+    /// Maximum signed value
+    MaximumValueSigned => 3,
+    /// Minimum unsigned value
+    MinimumValueUnsigned => 4,
+    /// Maximum unsigned value
+    MaximumValueUnsigned => 5,
+    /// Minimum length
+    MinimumLength => 6,
+    /// Maximum length
+    MaximumLength => 7,
+    /// Policy index
+    PolicyIndex => 8,
+    /// Policy maximum attribute type
+    PolicyMaximumType => 9,
+    /// Valid bits of a 32-bit bitfield
+    Bitfield32Mask => 10,
+    /// Padding
+    Padding => 11,
+    /// Valid bits of a mask
+    Mask => 12,
+);
+
+/// Policy of an attribute as reported by the kernel
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AttributePolicy {
+    /// Attribute type, one of the kernel `NL_ATTR_TYPE_*` values
+    pub kind: Option<u32>,
+    /// Minimum value of a signed integer
+    pub minimum_signed: Option<i64>,
+    /// Maximum value of a signed integer
+    pub maximum_signed: Option<i64>,
+    /// Minimum value of an unsigned integer
+    pub minimum_unsigned: Option<u64>,
+    /// Maximum value of an unsigned integer
+    pub maximum_unsigned: Option<u64>,
+    /// Minimum length of the attribute data
+    pub minimum_length: Option<u32>,
+    /// Maximum length of the attribute data
+    pub maximum_length: Option<u32>,
+    /// Valid bits
+    pub mask: Option<u64>,
+}
+
+impl AttributePolicy {
+    fn from_bytes(data: &[u8]) -> AttributePolicy {
+        let (_, attributes) = Attribute::unpack_all(data);
+        let mut policy = AttributePolicy::default();
+        for attr in attributes {
+            match PolicyAttributeId::from(attr.attribute_type()) {
+                PolicyAttributeId::Type => policy.kind = attr.as_u32().ok(),
+                PolicyAttributeId::MinimumValueSigned => policy.minimum_signed = attr.as_i64().ok(),
+                PolicyAttributeId::MaximumValueSigned => policy.maximum_signed = attr.as_i64().ok(),
+                PolicyAttributeId::MinimumValueUnsigned => {
+                    policy.minimum_unsigned = attr.as_u64().ok()
+                }
+                PolicyAttributeId::MaximumValueUnsigned => {
+                    policy.maximum_unsigned = attr.as_u64().ok()
+                }
+                PolicyAttributeId::MinimumLength => policy.minimum_length = attr.as_u32().ok(),
+                PolicyAttributeId::MaximumLength => policy.maximum_length = attr.as_u32().ok(),
+                PolicyAttributeId::Bitfield32Mask => {
+                    policy.mask = attr.as_u32().ok().map(u64::from)
+                }
+                PolicyAttributeId::Mask => policy.mask = attr.as_u64().ok(),
+                _ => {}
+            }
+        }
+        policy
+    }
+}
+
+/// Error reported by the kernel, including the extended acknowledge
+/// information
+///
+/// The kernel adds the extended information when the socket has the
+/// `NETLINK_EXT_ACK` option set, which is the default for
+/// [Socket](struct.Socket.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtendedAck {
+    /// Error code, a negated errno value
+    pub code: i32,
+    /// Human readable error message
+    pub message: Option<String>,
+    /// Offset of the rejected attribute, counted from the start of the
+    /// header of the original message
+    pub offset: Option<u32>,
+    /// Identifier of the rejected attribute, only known if the original
+    /// message was included in the error, see `NETLINK_CAP_ACK`
+    pub attribute: Option<u16>,
+    /// Subsystem specific cookie
+    pub cookie: Option<Vec<u8>>,
+    /// Policy of the rejected attribute
+    pub policy: Option<AttributePolicy>,
+    /// Type of the missing attribute
+    pub missing_type: Option<u32>,
+    /// Offset of the nest in which the attribute was missing
+    pub missing_nest: Option<u32>,
+}
+
+impl ExtendedAck {
+    /// Parse extended acknowledge attributes
+    ///
+    /// The original message is used to find the rejected attribute, if
+    /// available.
+    pub(crate) fn unpack(code: i32, data: &[u8], original: Option<&[u8]>) -> ExtendedAck {
+        let (_, attributes) = Attribute::unpack_all(data);
+        let mut ack = ExtendedAck {
+            code,
+            ..Default::default()
+        };
+        for attr in attributes {
+            match AcknowledgeAttributeId::from(attr.attribute_type()) {
+                AcknowledgeAttributeId::Unused => {}
+                AcknowledgeAttributeId::Message => ack.message = attr.as_string().ok(),
+                AcknowledgeAttributeId::Offset => ack.offset = attr.as_u32().ok(),
+                AcknowledgeAttributeId::Cookie => ack.cookie = Some(attr.as_bytes()),
+                AcknowledgeAttributeId::Policy => {
+                    ack.policy = Some(AttributePolicy::from_bytes(&attr.as_bytes()))
+                }
+                AcknowledgeAttributeId::MissingType => ack.missing_type = attr.as_u32().ok(),
+                AcknowledgeAttributeId::MissingNest => ack.missing_nest = attr.as_u32().ok(),
+            }
+        }
+        if let (Some(offset), Some(original)) = (ack.offset, original) {
+            let offset = offset as usize;
+            if offset >= Header::HEADER_SIZE && offset + 4 <= original.len() {
+                ack.attribute = u16::unpack(&original[offset + 2..])
+                    .ok()
+                    .map(|id| id & NLA_TYPE_MASK);
+            }
+        }
+        ack
+    }
+
+    /// Check if the kernel provided any extended information
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none()
+            && self.offset.is_none()
+            && self.cookie.is_none()
+            && self.policy.is_none()
+            && self.missing_type.is_none()
+            && self.missing_nest.is_none()
+    }
+
+    /// Get the error code as an io::Error
+    pub fn os_error(&self) -> io::Error {
+        io::Error::from_raw_os_error(-self.code)
+    }
+}
+
+impl fmt::Display for ExtendedAck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.os_error())?;
+        if let Some(ref message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(attribute) = self.attribute {
+            write!(f, ", attribute {}", attribute)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if let Some(missing) = self.missing_type {
+            write!(f, ", missing attribute {}", missing)?;
+        }
+        Ok(())
+    }
+}
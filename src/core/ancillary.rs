@@ -0,0 +1,83 @@
+use std::mem::size_of;
+use std::ptr;
+
+use libc;
+
+use crate::core::socket::{NETLINK_LISTEN_ALL_NSID, NETLINK_PKTINFO};
+use crate::core::system::Address;
+
+/// Size of the buffer receiving ancillary data, in 64-bit words
+pub(crate) const CONTROL_BUFFER_WORDS: usize = 16;
+
+/// Credentials of the process which sent a datagram, `SCM_CREDENTIALS`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Credentials {
+    /// Process identifier
+    pub pid: i32,
+    /// User identifier
+    pub uid: u32,
+    /// Group identifier
+    pub gid: u32,
+}
+
+/// Information about a received datagram, taken from the sender address and
+/// the ancillary data
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReceiveInfo {
+    /// Port identifier of the sender, 0 if sent by the kernel
+    pub pid: u32,
+    /// Multi-cast group the datagram was sent to, 0 for unicast
+    ///
+    /// Only provided with `NETLINK_PKTINFO` enabled, see
+    /// [Socket::set_packet_info](struct.Socket.html#method.set_packet_info).
+    pub group: Option<u32>,
+    /// Identifier of the network namespace the datagram originates from
+    ///
+    /// Only provided with `NETLINK_LISTEN_ALL_NSID` enabled and for datagrams
+    /// from namespaces with an assigned identifier, see
+    /// [Socket::set_listen_all_nsid](struct.Socket.html#method.set_listen_all_nsid).
+    pub nsid: Option<i32>,
+    /// Credentials of the sending process
+    ///
+    /// Only provided with `SO_PASSCRED` enabled, see
+    /// [Socket::set_pass_credentials](struct.Socket.html#method.set_pass_credentials).
+    pub credentials: Option<Credentials>,
+}
+
+impl ReceiveInfo {
+    /// Parse the ancillary data of a received message header
+    pub(crate) fn from_message_header(header: &libc::msghdr) -> ReceiveInfo {
+        let mut info = ReceiveInfo::default();
+        if header.msg_namelen as usize >= size_of::<Address>() {
+            info.pid = unsafe { (*(header.msg_name as *const Address)).pid };
+        }
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(header);
+            while !cmsg.is_null() {
+                let data = libc::CMSG_DATA(cmsg);
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS
+                {
+                    let ucred = ptr::read_unaligned(data as *const libc::ucred);
+                    info.credentials = Some(Credentials {
+                        pid: ucred.pid,
+                        uid: ucred.uid,
+                        gid: ucred.gid,
+                    });
+                } else if (*cmsg).cmsg_level == libc::SOL_NETLINK {
+                    match (*cmsg).cmsg_type {
+                        NETLINK_PKTINFO => {
+                            info.group = Some(ptr::read_unaligned(data as *const u32));
+                        }
+                        NETLINK_LISTEN_ALL_NSID => {
+                            info.nsid = Some(ptr::read_unaligned(data as *const i32));
+                        }
+                        _ => {}
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(header, cmsg);
+            }
+        }
+        info
+    }
+}
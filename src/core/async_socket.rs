@@ -0,0 +1,295 @@
+use std::future::{poll_fn, Future};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::{pin, Pin};
+use std::sync::{Mutex, MutexGuard};
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+
+use crate::core::message::{Message, Messages};
+use crate::core::socket::{ReceiveMode, Reply, SendMessage, Socket};
+use crate::core::Protocol;
+use crate::errors::{Error, Result};
+
+/// Netlink socket for use with the tokio runtime
+///
+/// Wraps a [Socket](struct.Socket.html) in non-blocking mode and registers it
+/// with the tokio reactor. Replies are matched to requests using the sequence
+/// number, so several requests can be in flight at the same time. Note that
+/// the kernel only runs one dump at a time per socket, a concurrent dump
+/// fails with `EBUSY`. Multi-cast notifications are queued separately and
+/// delivered by the `multicast` stream.
+pub struct AsyncSocket {
+    // Dropped before the socket, which closes the descriptor, so that it is
+    // deregistered from the reactor while still open
+    fd: AsyncFd<RawFd>,
+    socket: Mutex<Socket>,
+    received: Notify,
+}
+
+impl AsyncSocket {
+    /// Create a new AsyncSocket
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(protocol: Protocol) -> Result<AsyncSocket> {
+        AsyncSocket::from_socket(Socket::new(protocol)?)
+    }
+
+    /// Create a new AsyncSocket which subscribes to the provided multi-cast
+    /// groups
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new_multicast(protocol: Protocol, groups: &[u32]) -> Result<AsyncSocket> {
+        AsyncSocket::from_socket(Socket::new_multicast(protocol, groups)?)
+    }
+
+    /// Create a new AsyncSocket from a Socket, the socket is switched into
+    /// non-blocking mode
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn from_socket(mut socket: Socket) -> Result<AsyncSocket> {
+        socket.set_receive_mode(ReceiveMode::NonBlocking)?;
+        let fd = AsyncFd::new(socket.as_raw_fd())?;
+        Ok(AsyncSocket {
+            fd,
+            socket: Mutex::new(socket),
+            received: Notify::new(),
+        })
+    }
+
+    /// Subscribe to the multi-cast group provided
+    pub fn multicast_group_subscribe(&self, group: u32) -> Result<()> {
+        self.lock().multicast_group_subscribe(group)
+    }
+
+    /// Unsubscribe from the multi-cast group provided
+    pub fn multicast_group_unsubscribe(&self, group: u32) -> Result<()> {
+        self.lock().multicast_group_unsubscribe(group)
+    }
+
+    /// Send the provided message and collect the replies
+    ///
+    /// Returns once the request has been completed, that is after the reply,
+    /// the acknowledge or the end of the dump depending on the message flags.
+    pub async fn request<S: SendMessage>(&self, message: S) -> Result<Messages> {
+        let sequence = self.send(&message).await?;
+        let mut messages = vec![];
+        while let Some(message) = self.next_message(Some(sequence)).await? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    /// Send the provided dump request and stream the replies
+    ///
+    /// The stream ends after the end of the dump. Dropping the stream before
+    /// the end cancels the dump, the remaining replies are discarded as they
+    /// are received.
+    pub async fn dump<S: SendMessage>(&self, message: S) -> Result<MessageStream<'_>> {
+        let sequence = self.send(&message).await?;
+        Ok(MessageStream::new(self, Some(sequence)))
+    }
+
+    /// Stream multi-cast notifications received on the socket
+    ///
+    /// The stream never ends, unless an error occurs. After an
+    /// [Error::Overrun](enum.Error.html) notifications have been lost, a new
+    /// stream can be created to continue after re-synchronizing the state.
+    pub fn multicast(&self) -> MessageStream<'_> {
+        MessageStream::new(self, None)
+    }
+
+    async fn send<S: SendMessage>(&self, message: &S) -> Result<u32> {
+        loop {
+            let mut guard = self.fd.writable().await?;
+            match self.lock().send_message(message) {
+                Err(Error::Io(ref err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                    guard.clear_ready();
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Get the next reply for the request with the provided sequence number,
+    /// or the next notification if no sequence number is provided
+    async fn next_message(&self, sequence: Option<u32>) -> Result<Option<Message>> {
+        loop {
+            // Created before checking the queues so that no wake-up is lost
+            let received = self.received.notified();
+            {
+                let mut socket = self.lock();
+                match sequence {
+                    Some(sequence) => match socket.next_reply(sequence)? {
+                        Reply::Message(message) => return Ok(Some(message)),
+                        Reply::Done => return Ok(None),
+                        Reply::Pending => {}
+                    },
+                    None => {
+                        if let Some(message) = socket.next_notification() {
+                            return Ok(Some(message));
+                        }
+                    }
+                }
+            }
+            self.receive(received).await?;
+        }
+    }
+
+    /// Wait for data to receive, or until another task has received data
+    async fn receive(&self, received: Notified<'_>) -> Result<()> {
+        let mut received = pin!(received);
+        let mut readable = pin!(self.fd.readable());
+        let guard = poll_fn(|cx| {
+            if received.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Ok(None));
+            }
+            readable.as_mut().poll(cx).map(|r| r.map(Some))
+        })
+        .await?;
+        if let Some(mut guard) = guard {
+            let result = self.lock().receive_once();
+            match result {
+                Ok(false) => guard.clear_ready(),
+                Ok(true) => self.received.notify_waiters(),
+                Err(err) => {
+                    self.received.notify_waiters();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Socket> {
+        self.socket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AsRawFd for AsyncSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        *self.fd.get_ref()
+    }
+}
+
+type NextMessage<'a> = Pin<Box<dyn Future<Output = Result<Option<Message>>> + Send + 'a>>;
+
+/// Stream of messages received on an [AsyncSocket](struct.AsyncSocket.html)
+pub struct MessageStream<'a> {
+    socket: &'a AsyncSocket,
+    sequence: Option<u32>,
+    next: Option<NextMessage<'a>>,
+    finished: bool,
+}
+
+impl<'a> MessageStream<'a> {
+    fn new(socket: &'a AsyncSocket, sequence: Option<u32>) -> MessageStream<'a> {
+        MessageStream {
+            socket,
+            sequence,
+            next: None,
+            finished: false,
+        }
+    }
+}
+
+impl<'a> Stream for MessageStream<'a> {
+    type Item = Result<Message>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+        let (socket, sequence) = (this.socket, this.sequence);
+        let next = this
+            .next
+            .get_or_insert_with(|| Box::pin(socket.next_message(sequence)));
+        let result = ready!(next.as_mut().poll(cx));
+        this.next = None;
+        match result {
+            Ok(Some(message)) => Poll::Ready(Some(Ok(message))),
+            Ok(None) => {
+                this.finished = true;
+                Poll::Ready(None)
+            }
+            Err(err) => {
+                this.finished = true;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for MessageStream<'a> {
+    fn drop(&mut self) {
+        if let (Some(sequence), false) = (self.sequence, self.finished) {
+            self.socket.lock().cancel(sequence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Attribute, MessageMode};
+    use crate::{generic, route};
+    use std::sync::Arc;
+
+    async fn collect(mut stream: MessageStream<'_>) -> Vec<Message> {
+        let mut messages = vec![];
+        while let Some(m) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            messages.push(m.unwrap());
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn async_route_get_link() {
+        let socket = Arc::new(AsyncSocket::new(Protocol::Route).unwrap());
+        let request_socket = socket.clone();
+        let messages = tokio::spawn(async move {
+            let msg = route::Message::new(route::FamilyId::GetLink);
+            request_socket.request(msg).await.unwrap()
+        })
+        .await
+        .unwrap();
+        assert!(!messages.is_empty());
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        let dumped = collect(socket.dump(msg).await.unwrap()).await;
+        assert_eq!(dumped.len(), messages.len());
+        for m in dumped {
+            assert_eq!(m.header.identifier, route::FamilyId::NewLink);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn async_concurrent_requests() {
+        let socket = Arc::new(AsyncSocket::new(Protocol::Generic).unwrap());
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let socket = socket.clone();
+                tokio::spawn(async move {
+                    let mut msg = generic::Message::new(
+                        generic::FamilyId::Control,
+                        generic::Command::GetFamily,
+                        MessageMode::Acknowledge,
+                    );
+                    msg.append_attribute(Attribute::new_string_with_nul(
+                        generic::AttributeId::FamilyName,
+                        "nlctrl",
+                    ));
+                    socket.request(msg).await.unwrap()
+                })
+            })
+            .collect();
+        for task in tasks {
+            let messages = task.await.unwrap();
+            assert_eq!(messages.len(), 1);
+        }
+    }
+}
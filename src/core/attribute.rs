@@ -1,12 +1,21 @@
 use std::ffi::{CStr, CString};
+use std::fmt;
 use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str;
 
 use crate::core::hardware_address::HardwareAddress;
-use crate::core::message::netlink_padding;
-use crate::core::pack::{NativePack, NativeUnpack};
+use crate::core::message::{netlink_align, netlink_padding};
+use crate::core::pack::{pack_vec, NativePack, NativeUnpack};
 use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
 
+/// Mask of the attribute type, the remaining bits are flags
+pub(crate) const NLA_TYPE_MASK: u16 = 0x3fff;
+/// The attribute data are nested attributes
+pub const NLA_F_NESTED: u16 = 0x8000;
+/// The attribute data is in network byte order
+pub(crate) const NLA_F_NET_BYTEORDER: u16 = 0x4000;
+
 /// Parsing an array of nested attributes
 ///
 /// Each chunk of attributes has a size and an index, the size is the size of
@@ -18,24 +27,50 @@ use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
 /// ---------------------------------------------------------------
 ///    u16    u16    u8 * (size - 4)
 /// ```
+///
+/// See [Attribute::new_nested_array](struct.Attribute.html#method.new_nested_array)
+/// for the encoder.
 pub fn nested_attribute_array(data: &[u8]) -> Vec<Vec<Attribute>> {
     let vs = mem::size_of::<u16>();
     let mut attrs = vec![];
     let mut d = &data[..];
-    while d.len() > (vs * 2) {
-        let size = u16::unpack(&d).unwrap();
+    while d.len() >= (vs * 2) {
+        let size = u16::unpack(d).unwrap() as usize;
         let _index = u16::unpack(&d[vs..]).unwrap();
-        if d.len() > size as usize {
-            let (_, attributes) = Attribute::unpack_all(&d[(vs * 2)..size as usize]);
-            attrs.push(attributes);
-        } else {
+        if size < vs * 2 || d.len() < size {
             break;
         }
-        d = &d[size as usize..];
+        let (_, attributes) = Attribute::unpack_all(&d[(vs * 2)..size]);
+        attrs.push(attributes);
+        d = &d[netlink_align(size).min(d.len())..];
     }
     attrs
 }
 
+/// Get the size of the packed attributes, including padding
+pub(crate) fn attributes_pack_size(attributes: &[Attribute]) -> usize {
+    attributes
+        .iter()
+        .map(|attr| netlink_align(attr.pack_size()))
+        .sum()
+}
+
+/// Unpack an IPv4 address, the data has to be exactly 4 bytes
+fn unpack_ipv4(data: &[u8]) -> Result<Ipv4Addr> {
+    match IpAddr::unpack(data)? {
+        IpAddr::V4(address) => Ok(address),
+        IpAddr::V6(_) => Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into()),
+    }
+}
+
+/// Unpack an IPv6 address, the data has to be exactly 16 bytes
+fn unpack_ipv6(data: &[u8]) -> Result<Ipv6Addr> {
+    match IpAddr::unpack(data)? {
+        IpAddr::V6(address) => Ok(address),
+        IpAddr::V4(_) => Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into()),
+    }
+}
+
 /// Netlink attribute
 ///
 /// ```text
@@ -45,7 +80,7 @@ pub fn nested_attribute_array(data: &[u8]) -> Vec<Vec<Attribute>> {
 /// ```
 ///
 /// The data is 4 byte aligned.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Attribute {
     /// Attribute identifier
     pub identifier: u16,
@@ -100,9 +135,52 @@ impl Attribute {
         }
     }
 
+    /// Create a new nested attribute holding the provided attributes
+    ///
+    /// The `NLA_F_NESTED` flag is set on the identifier, as required by
+    /// kernels with strict attribute validation. Panics if the attributes do
+    /// not fit, the length of an attribute including its header is a u16.
+    pub fn new_nested<ID: Into<u16>>(identifier: ID, attributes: Vec<Attribute>) -> Attribute {
+        let size = attributes_pack_size(&attributes);
+        assert!(
+            size <= u16::MAX as usize - Attribute::HEADER_SIZE,
+            "nested attributes too large"
+        );
+        let mut data = vec![0u8; size];
+        // The buffer fits the attributes, packing cannot fail
+        pack_vec(&mut data, &attributes).unwrap();
+        Attribute {
+            identifier: identifier.into() | NLA_F_NESTED,
+            data,
+        }
+    }
+
+    /// Create a new nested attribute holding an indexed array of nested
+    /// attributes
+    ///
+    /// Each element is a nested attribute with the index, starting at 1, as
+    /// identifier. Decode with `nested_attribute_array`.
+    pub fn new_nested_array<ID: Into<u16>>(
+        identifier: ID,
+        elements: Vec<Vec<Attribute>>,
+    ) -> Attribute {
+        let elements = elements
+            .into_iter()
+            .enumerate()
+            .map(|(index, attributes)| Attribute::new_nested(index as u16 + 1, attributes))
+            .collect();
+        Attribute::new_nested(identifier, elements)
+    }
+
+    /// Create a new IP address attribute, 4 bytes for IPv4 or 16 bytes for
+    /// IPv6
+    pub fn new_ip<ID: Into<u16>, A: Into<IpAddr>>(identifier: ID, address: A) -> Attribute {
+        Attribute::new(identifier, address.into())
+    }
+
     /// Create a new attribute from a type that can be packed into a byte slice
     pub fn new<ID: Into<u16>, V: NativePack>(identifier: ID, value: V) -> Attribute {
-        let mut data = vec![0u8; mem::size_of::<V>()];
+        let mut data = vec![0u8; value.pack_size()];
         value.pack_unchecked(&mut data);
         Attribute {
             identifier: identifier.into(),
@@ -110,9 +188,46 @@ impl Attribute {
         }
     }
 
+    /// Borrow the attribute, the accessors decode through the borrowed form
+    pub fn as_attribute_ref(&self) -> AttributeRef<'_> {
+        AttributeRef {
+            identifier: self.identifier,
+            data: &self.data,
+        }
+    }
+
+    /// Get the attribute type, the identifier without the flag bits
+    ///
+    /// Use this to match the attribute against an identifier enumeration.
+    pub fn attribute_type(&self) -> u16 {
+        self.as_attribute_ref().attribute_type()
+    }
+
+    /// Check if the data are nested attributes, `NLA_F_NESTED`
+    ///
+    /// Older kernels do not set this flag for all nested attributes.
+    pub fn is_nested(&self) -> bool {
+        self.as_attribute_ref().is_nested()
+    }
+
+    /// Check if the data is in network byte order, `NLA_F_NET_BYTEORDER`
+    pub fn is_network_byte_order(&self) -> bool {
+        self.as_attribute_ref().is_network_byte_order()
+    }
+
+    /// Unpack the data as nested attributes
+    pub fn as_nested(&self) -> Vec<Attribute> {
+        Attribute::unpack_all(&self.data).1
+    }
+
+    /// Iterate over the nested attributes without copying them
+    pub fn nested(&self) -> AttributeIter<'_> {
+        self.as_attribute_ref().nested()
+    }
+
     /// Get the length of the data
     pub fn len(&self) -> u16 {
-        self.data.len() as u16
+        self.as_attribute_ref().len()
     }
     /// Get the length of the data and header
     pub fn total_len(&self) -> usize {
@@ -120,57 +235,225 @@ impl Attribute {
     }
     /// Unpack the underlying data into a u8
     pub fn as_u8(&self) -> Result<u8> {
-        u8::unpack(&self.data)
+        self.as_attribute_ref().as_u8()
     }
     /// Unpack the underlying data into a u16
     pub fn as_u16(&self) -> Result<u16> {
-        u16::unpack(&self.data)
+        self.as_attribute_ref().as_u16()
     }
     /// Unpack the underlying data into a u32
     pub fn as_u32(&self) -> Result<u32> {
-        u32::unpack(&self.data)
+        self.as_attribute_ref().as_u32()
     }
     /// Unpack the underlying data into a u64
     pub fn as_u64(&self) -> Result<u64> {
-        u64::unpack(&self.data)
+        self.as_attribute_ref().as_u64()
     }
     /// Unpack the underlying data into a i8
     pub fn as_i8(&self) -> Result<i8> {
-        i8::unpack(&self.data)
+        self.as_attribute_ref().as_i8()
     }
     /// Unpack the underlying data into a i16
     pub fn as_i16(&self) -> Result<i16> {
-        i16::unpack(&self.data)
+        self.as_attribute_ref().as_i16()
     }
     /// Unpack the underlying data into a i32
     pub fn as_i32(&self) -> Result<i32> {
-        i32::unpack(&self.data)
+        self.as_attribute_ref().as_i32()
     }
     /// Unpack the underlying data into a i64
     pub fn as_i64(&self) -> Result<i64> {
-        i64::unpack(&self.data)
+        self.as_attribute_ref().as_i64()
     }
-    /// Unpack the underlying data into a String
+    /// Unpack the underlying data into a String, without the nul terminator
     pub fn as_string(&self) -> Result<String> {
-        match CStr::from_bytes_with_nul(&self.data) {
-            Ok(bytes) => {
-                let s = bytes.to_str()?;
-                Ok(String::from(s))
-            }
-            Err(_) => {
-                let s = String::from_utf8(self.data.clone())?;
-                Ok(s)
-            }
-        }
+        self.as_attribute_ref().as_str().map(String::from)
     }
     /// Unpack the underlying data into a HardwareAddress
     pub fn as_hardware_address(&self) -> Result<HardwareAddress> {
-        HardwareAddress::unpack(&self.data)
+        self.as_attribute_ref().as_hardware_address()
+    }
+    /// Unpack the underlying data into a Ipv4Addr
+    pub fn as_ipv4(&self) -> Result<Ipv4Addr> {
+        self.as_attribute_ref().as_ipv4()
+    }
+    /// Unpack the underlying data into a Ipv6Addr
+    pub fn as_ipv6(&self) -> Result<Ipv6Addr> {
+        self.as_attribute_ref().as_ipv6()
+    }
+    /// Unpack the underlying data into a IpAddr, the length of the data
+    /// gives the address family
+    pub fn as_ip(&self) -> Result<IpAddr> {
+        self.as_attribute_ref().as_ip()
     }
     /// Get a clone of the underlying data
     pub fn as_bytes(&self) -> Vec<u8> {
         self.data.clone()
     }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Identifier: {} Length: {} Data:",
+            self.identifier,
+            self.len()
+        )?;
+        for byte in &self.data {
+            write!(f, " {:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Netlink attribute borrowed from a buffer
+///
+/// Same as [Attribute](struct.Attribute.html), but the data refers to the
+/// buffer the attribute was unpacked from instead of being copied.
+#[derive(Clone, Copy, Debug)]
+pub struct AttributeRef<'a> {
+    /// Attribute identifier
+    pub identifier: u16,
+    data: &'a [u8],
+}
+
+impl<'a> AttributeRef<'a> {
+    /// Get the attribute type, the identifier without the flag bits
+    pub fn attribute_type(&self) -> u16 {
+        self.identifier & NLA_TYPE_MASK
+    }
+
+    /// Check if the data are nested attributes, `NLA_F_NESTED`
+    pub fn is_nested(&self) -> bool {
+        self.identifier & NLA_F_NESTED != 0
+    }
+
+    /// Check if the data is in network byte order, `NLA_F_NET_BYTEORDER`
+    pub fn is_network_byte_order(&self) -> bool {
+        self.identifier & NLA_F_NET_BYTEORDER != 0
+    }
+
+    /// Iterate over the nested attributes
+    pub fn nested(&self) -> AttributeIter<'a> {
+        AttributeIter::new(self.data)
+    }
+
+    /// Get the length of the data
+    pub fn len(&self) -> u16 {
+        self.data.len() as u16
+    }
+    /// Check if the attribute has no data
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// Unpack the underlying data into a u8
+    pub fn as_u8(&self) -> Result<u8> {
+        u8::unpack(self.data)
+    }
+    /// Unpack the underlying data into a u16
+    pub fn as_u16(&self) -> Result<u16> {
+        u16::unpack(self.data)
+    }
+    /// Unpack the underlying data into a u32
+    pub fn as_u32(&self) -> Result<u32> {
+        u32::unpack(self.data)
+    }
+    /// Unpack the underlying data into a u64
+    pub fn as_u64(&self) -> Result<u64> {
+        u64::unpack(self.data)
+    }
+    /// Unpack the underlying data into a i8
+    pub fn as_i8(&self) -> Result<i8> {
+        i8::unpack(self.data)
+    }
+    /// Unpack the underlying data into a i16
+    pub fn as_i16(&self) -> Result<i16> {
+        i16::unpack(self.data)
+    }
+    /// Unpack the underlying data into a i32
+    pub fn as_i32(&self) -> Result<i32> {
+        i32::unpack(self.data)
+    }
+    /// Unpack the underlying data into a i64
+    pub fn as_i64(&self) -> Result<i64> {
+        i64::unpack(self.data)
+    }
+    /// Get the underlying data as a string, without the nul terminator
+    pub fn as_str(&self) -> Result<&'a str> {
+        let data = match CStr::from_bytes_until_nul(self.data) {
+            Ok(bytes) => bytes.to_bytes(),
+            Err(_) => self.data,
+        };
+        Ok(str::from_utf8(data)?)
+    }
+    /// Unpack the underlying data into a HardwareAddress
+    pub fn as_hardware_address(&self) -> Result<HardwareAddress> {
+        HardwareAddress::unpack(self.data)
+    }
+    /// Unpack the underlying data into a Ipv4Addr
+    pub fn as_ipv4(&self) -> Result<Ipv4Addr> {
+        unpack_ipv4(self.data)
+    }
+    /// Unpack the underlying data into a Ipv6Addr
+    pub fn as_ipv6(&self) -> Result<Ipv6Addr> {
+        unpack_ipv6(self.data)
+    }
+    /// Unpack the underlying data into a IpAddr, the length of the data
+    /// gives the address family
+    pub fn as_ip(&self) -> Result<IpAddr> {
+        IpAddr::unpack(self.data)
+    }
+    /// Get the underlying data
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+    /// Copy the attribute
+    pub fn to_attribute(&self) -> Attribute {
+        Attribute::new_bytes(self.identifier, self.data)
+    }
+}
+
+/// Iterator over attributes, borrowing the buffer
+///
+/// Iteration ends at the first malformed attribute, as with
+/// `Attribute::unpack_all`.
+#[derive(Clone)]
+pub struct AttributeIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AttributeIter<'a> {
+    /// Create an iterator over the attributes in the buffer
+    pub fn new(data: &'a [u8]) -> AttributeIter<'a> {
+        AttributeIter { data }
+    }
+}
+
+impl<'a> Iterator for AttributeIter<'a> {
+    type Item = AttributeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.data;
+        if data.len() < Attribute::HEADER_SIZE {
+            return None;
+        }
+        let length = u16::unpack_unchecked(data) as usize;
+        if length < Attribute::HEADER_SIZE || length > data.len() {
+            self.data = &[];
+            return None;
+        }
+        // The padding of the last attribute may be missing
+        self.data = &data[netlink_align(length).min(data.len())..];
+        Some(AttributeRef {
+            identifier: u16::unpack_unchecked(&data[2..]),
+            data: &data[Attribute::HEADER_SIZE..length],
+        })
+    }
 }
 
 impl NativePack for Attribute {
@@ -178,11 +461,17 @@ impl NativePack for Attribute {
         self.total_len()
     }
     fn pack<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a mut [u8]> {
+        if self.total_len() > u16::MAX as usize {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into());
+        }
         let length = self.total_len() as u16;
         let slice = length.pack(buffer)?;
         let slice = self.identifier.pack(slice)?;
         let slice = self.data.pack(slice)?;
         let padding = netlink_padding(self.data.len());
+        if slice.len() < padding {
+            return Err(NetlinkError::new(NetlinkErrorKind::NotEnoughData).into());
+        }
         Ok(&mut slice[padding..])
     }
     fn pack_unchecked(&self, buffer: &mut [u8]) {
@@ -200,6 +489,9 @@ impl NativeUnpack for Attribute {
         }
         let length = u16::unpack_unchecked(buffer) as usize;
         let identifier = u16::unpack_unchecked(&buffer[2..]);
+        if length < Attribute::HEADER_SIZE {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into());
+        }
 
         let padding = netlink_padding(length);
         if buffer.len() < (length + padding) {
@@ -228,6 +520,7 @@ impl NativeUnpack for Attribute {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::pack::BigEndian;
 
     #[test]
     fn unpack_attribute() {
@@ -308,4 +601,129 @@ mod tests {
         }
         assert_eq!(&buffer[0..8], data);
     }
+
+    #[test]
+    fn nested_attribute() {
+        let attr = Attribute::new_nested(
+            3u16,
+            vec![
+                Attribute::new_bytes(1u16, &[0x11, 0x22, 0x33]),
+                Attribute::new(2u16, 0x1234_5678u32),
+            ],
+        );
+        assert_eq!(attr.identifier, 0x8003);
+        assert_eq!(attr.attribute_type(), 3);
+        assert!(attr.is_nested());
+        assert!(!attr.is_network_byte_order());
+        assert_eq!(attr.len(), 16);
+        let nested = attr.as_nested();
+        assert_eq!(nested.len(), 2);
+        assert_eq!(nested[0].as_bytes(), vec![0x11, 0x22, 0x33]);
+        assert_eq!(nested[1].as_u32().unwrap(), 0x1234_5678);
+    }
+
+    #[test]
+    fn nested_array_roundtrip() {
+        let elements = vec![
+            vec![Attribute::new_bytes(1u16, &[0xaa])],
+            vec![
+                Attribute::new_string(1u16, "eth0"),
+                Attribute::new(2u16, 7u16),
+            ],
+        ];
+        let attr = Attribute::new_nested_array(5u16, elements);
+        assert_eq!(attr.attribute_type(), 5);
+        let indexes: Vec<u16> = attr
+            .as_nested()
+            .iter()
+            .map(|element| element.attribute_type())
+            .collect();
+        assert_eq!(indexes, vec![1, 2]);
+        let decoded = nested_attribute_array(&attr.as_bytes());
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0][0].as_bytes(), vec![0xaa]);
+        assert_eq!(decoded[1][0].as_string().unwrap(), "eth0");
+        assert_eq!(decoded[1][1].as_u16().unwrap(), 7);
+    }
+
+    #[test]
+    fn nested_array_truncated() {
+        assert!(nested_attribute_array(&[0, 0, 1, 0]).is_empty());
+        assert!(nested_attribute_array(&[12, 0, 1, 0, 8, 0]).is_empty());
+    }
+
+    #[test]
+    fn iterate_attributes() {
+        let data = [
+            0x06, 0x00, // size
+            0x01, 0x40, // identifier
+            0x12, 0x34, // data
+            0x00, 0x00, // padding
+            0x08, 0x00, // size
+            0x02, 0x00, // identifier
+            0x65, 0x74, 0x68, 0x00, // data
+        ];
+        let attributes: Vec<_> = AttributeIter::new(&data).collect();
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes[0].attribute_type(), 1);
+        assert!(attributes[0].is_network_byte_order());
+        assert_eq!(attributes[0].as_bytes(), &[0x12, 0x34]);
+        assert_eq!(attributes[1].as_str().unwrap(), "eth");
+        assert_eq!(attributes[1].to_attribute().as_string().unwrap(), "eth");
+        assert_eq!(AttributeIter::new(&data[..10]).count(), 1);
+    }
+
+    #[test]
+    fn ip_address_attribute() {
+        let attr = Attribute::new_ip(1u16, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(attr.as_bytes(), vec![10, 0, 0, 1]);
+        assert_eq!(attr.as_ipv4().unwrap(), Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(attr.as_ip().unwrap(), IpAddr::from([10, 0, 0, 1]));
+        assert!(attr.as_ipv6().is_err());
+
+        let address = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let attr = Attribute::new_ip(2u16, IpAddr::V6(address));
+        assert_eq!(attr.len(), 16);
+        assert_eq!(attr.as_ipv6().unwrap(), address);
+        let mut data = vec![0u8; attr.pack_size()];
+        attr.pack_unchecked(&mut data);
+        let attr = AttributeIter::new(&data).next().unwrap();
+        assert_eq!(attr.as_ip().unwrap(), IpAddr::V6(address));
+
+        let attr = Attribute::new(3u16, BigEndian(443u16));
+        assert_eq!(attr.as_bytes(), vec![0x01, 0xbb]);
+
+        // Only addresses of the exact size are accepted
+        for size in [3, 5, 8, 15, 17] {
+            let attr = Attribute::new_bytes(4u16, &vec![1u8; size]);
+            assert!(attr.as_ipv4().is_err());
+            assert!(attr.as_ipv6().is_err());
+            assert!(attr.as_ip().is_err());
+        }
+        let data = [8, 0, 4, 0, 1, 2, 3, 4, 5];
+        let attr = AttributeIter::new(&data).next().unwrap();
+        assert!(attr.as_ipv6().is_err());
+        assert_eq!(attr.as_ipv4().unwrap(), Ipv4Addr::new(1, 2, 3, 4));
+        assert_eq!(IpAddr::unpack_unchecked(&[0u8; 16]), Ipv6Addr::UNSPECIFIED);
+    }
+
+    #[test]
+    #[should_panic(expected = "nested attributes too large")]
+    fn nested_too_large() {
+        let data = vec![0u8; 40000];
+        let attributes = vec![
+            Attribute::new_bytes(1u16, &data),
+            Attribute::new_bytes(2u16, &data),
+        ];
+        Attribute::new_nested(3u16, attributes);
+    }
+
+    #[test]
+    fn pack_too_large() {
+        let attr = Attribute::new_bytes(1u16, &vec![0u8; u16::MAX as usize]);
+        let mut buffer = vec![0u8; attr.pack_size() + 4];
+        assert!(attr.pack(&mut buffer).is_err());
+        let attr = Attribute::new_bytes(1u16, &vec![0u8; u16::MAX as usize - 4]);
+        assert!(attr.pack(&mut buffer).is_ok());
+    }
 }
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::core::attribute::Attribute;
+use crate::core::hardware_address::HardwareAddress;
+use crate::core::pack::{BigEndian, NativeUnpack};
+use crate::errors::{Error, Result};
+
+/// Conversion of attribute data into a value, see
+/// [AttributeSet::get](struct.AttributeSet.html#method.get)
+pub trait FromAttribute: Sized {
+    /// Convert the attribute data into a value
+    fn from_attribute(attribute: &Attribute) -> Result<Self>;
+}
+
+macro_rules! from_attribute {
+    ($ty:ty, $method:ident) => {
+        impl FromAttribute for $ty {
+            fn from_attribute(attribute: &Attribute) -> Result<Self> {
+                attribute.$method()
+            }
+        }
+    };
+}
+
+from_attribute!(u8, as_u8);
+from_attribute!(u16, as_u16);
+from_attribute!(u32, as_u32);
+from_attribute!(u64, as_u64);
+from_attribute!(i8, as_i8);
+from_attribute!(i16, as_i16);
+from_attribute!(i32, as_i32);
+from_attribute!(i64, as_i64);
+from_attribute!(String, as_string);
+from_attribute!(HardwareAddress, as_hardware_address);
+from_attribute!(Ipv4Addr, as_ipv4);
+from_attribute!(Ipv6Addr, as_ipv6);
+from_attribute!(IpAddr, as_ip);
+
+macro_rules! from_big_endian {
+    ($ty:ty) => {
+        impl FromAttribute for BigEndian<$ty> {
+            fn from_attribute(attribute: &Attribute) -> Result<Self> {
+                BigEndian::<$ty>::unpack(attribute.data())
+            }
+        }
+    };
+}
+
+from_big_endian!(u16);
+from_big_endian!(u32);
+from_big_endian!(u64);
+
+impl FromAttribute for Vec<u8> {
+    fn from_attribute(attribute: &Attribute) -> Result<Self> {
+        Ok(attribute.as_bytes())
+    }
+}
+
+impl FromAttribute for Vec<Attribute> {
+    fn from_attribute(attribute: &Attribute) -> Result<Self> {
+        Ok(attribute.as_nested())
+    }
+}
+
+impl<Id> FromAttribute for AttributeSet<Id> {
+    fn from_attribute(attribute: &Attribute) -> Result<Self> {
+        Ok(AttributeSet::new(attribute.as_nested()))
+    }
+}
+
+/// Conversion of a value into an attribute, the counterpart of
+/// [FromAttribute](trait.FromAttribute.html)
+pub trait ToAttribute {
+    /// Convert the value into an attribute with the provided identifier
+    fn to_attribute(&self, identifier: u16) -> Attribute;
+}
+
+macro_rules! to_attribute {
+    ($ty:ty) => {
+        impl ToAttribute for $ty {
+            fn to_attribute(&self, identifier: u16) -> Attribute {
+                Attribute::new(identifier, *self)
+            }
+        }
+    };
+}
+
+to_attribute!(u8);
+to_attribute!(u16);
+to_attribute!(u32);
+to_attribute!(u64);
+to_attribute!(i8);
+to_attribute!(i16);
+to_attribute!(i32);
+to_attribute!(i64);
+to_attribute!(HardwareAddress);
+to_attribute!(Ipv4Addr);
+to_attribute!(Ipv6Addr);
+to_attribute!(IpAddr);
+to_attribute!(BigEndian<u16>);
+to_attribute!(BigEndian<u32>);
+to_attribute!(BigEndian<u64>);
+
+impl ToAttribute for Vec<u8> {
+    fn to_attribute(&self, identifier: u16) -> Attribute {
+        Attribute::new_bytes(identifier, self)
+    }
+}
+
+impl ToAttribute for Vec<Attribute> {
+    fn to_attribute(&self, identifier: u16) -> Attribute {
+        Attribute::new_nested(identifier, self.clone())
+    }
+}
+
+/// Expected type of the attribute data, see
+/// [AttributeRule](struct.AttributeRule.html)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeKind {
+    /// Any data
+    Unspecified,
+    /// Flag without data
+    Flag,
+    /// 8-bit integer
+    U8,
+    /// 16-bit integer
+    U16,
+    /// 32-bit integer
+    U32,
+    /// 64-bit integer
+    U64,
+    /// UTF-8 string, optionally nul terminated
+    String,
+    /// Nul terminated UTF-8 string
+    NulString,
+    /// Nested attributes
+    Nested,
+}
+
+/// Validation rule for an attribute, similar to the kernel `nla_policy`
+///
+/// Rules are `const` constructible, so a policy can be a static table.
+///
+/// ```
+/// use netlink_rust::core::{AttributeKind, AttributeRule};
+///
+/// static POLICY: [AttributeRule; 2] = [
+///     AttributeRule::new(1, AttributeKind::U16),
+///     AttributeRule::new(2, AttributeKind::NulString).with_length(1, 16),
+/// ];
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AttributeRule {
+    /// Attribute type
+    pub identifier: u16,
+    /// Expected type of the data
+    pub kind: AttributeKind,
+    /// Minimum length of the data
+    pub minimum_length: usize,
+    /// Maximum length of the data, 0 for no limit
+    pub maximum_length: usize,
+}
+
+impl AttributeRule {
+    /// Create a new rule for the attribute type
+    pub const fn new(identifier: u16, kind: AttributeKind) -> AttributeRule {
+        AttributeRule {
+            identifier,
+            kind,
+            minimum_length: 0,
+            maximum_length: 0,
+        }
+    }
+
+    /// Limit the length of the data
+    pub const fn with_length(self, minimum: usize, maximum: usize) -> AttributeRule {
+        AttributeRule {
+            minimum_length: minimum,
+            maximum_length: maximum,
+            ..self
+        }
+    }
+
+    /// Check if the attribute follows the rule
+    pub fn check(&self, attribute: &Attribute) -> bool {
+        let data = attribute.data();
+        let valid = match self.kind {
+            AttributeKind::Unspecified => true,
+            AttributeKind::Flag => data.is_empty(),
+            AttributeKind::U8 => data.len() == 1,
+            AttributeKind::U16 => data.len() == 2,
+            AttributeKind::U32 => data.len() == 4,
+            AttributeKind::U64 => data.len() == 8,
+            AttributeKind::String => attribute.as_string().is_ok(),
+            AttributeKind::NulString => data.last() == Some(&0) && attribute.as_string().is_ok(),
+            AttributeKind::Nested => Attribute::unpack_all(data).0 == data.len(),
+        };
+        valid
+            && data.len() >= self.minimum_length
+            && (self.maximum_length == 0 || data.len() <= self.maximum_length)
+    }
+}
+
+/// Attributes indexed by attribute type
+///
+/// `Id` is the identifier enumeration of the attribute set, typically
+/// declared with `extended_enum_default!`. Lookups ignore the flag bits of
+/// the identifier. An attribute type may be repeated, `get` returns the last
+/// one as the kernel does, `get_all` returns all of them.
+///
+/// ```
+/// use netlink_rust::Attribute;
+/// use netlink_rust::core::AttributeSet;
+/// use netlink_rust::generic::AttributeId;
+///
+/// let attributes = vec![
+///     Attribute::new(AttributeId::FamilyId, 16u16),
+///     Attribute::new_string_with_nul(AttributeId::FamilyName, "nlctrl"),
+/// ];
+/// let set = AttributeSet::<AttributeId>::new(attributes);
+/// assert_eq!(set.require::<u16>(AttributeId::FamilyId).unwrap(), 16);
+/// assert_eq!(set.get::<u32>(AttributeId::Version).unwrap(), None);
+/// ```
+pub struct AttributeSet<Id> {
+    attributes: Vec<Attribute>,
+    index: HashMap<u16, Vec<usize>>,
+    _identifier: PhantomData<Id>,
+}
+
+impl<Id> AttributeSet<Id> {
+    /// Create a new set from the attributes
+    pub fn new(attributes: Vec<Attribute>) -> AttributeSet<Id> {
+        let mut index: HashMap<u16, Vec<usize>> = HashMap::new();
+        for (position, attribute) in attributes.iter().enumerate() {
+            index
+                .entry(attribute.attribute_type())
+                .or_default()
+                .push(position);
+        }
+        AttributeSet {
+            attributes,
+            index,
+            _identifier: PhantomData,
+        }
+    }
+
+    /// Unpack the set from a byte slice
+    pub fn unpack(data: &[u8]) -> AttributeSet<Id> {
+        AttributeSet::new(Attribute::unpack_all(data).1)
+    }
+
+    /// Get all attributes in the order they were received
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Get the number of attributes
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    /// Check if the set has no attributes
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    /// Take the attributes
+    pub fn into_attributes(self) -> Vec<Attribute> {
+        self.attributes
+    }
+}
+
+impl<Id: Into<u16> + From<u16> + fmt::Debug> AttributeSet<Id> {
+    /// Check if the attribute type is present
+    pub fn contains(&self, identifier: Id) -> bool {
+        self.index.contains_key(&identifier.into())
+    }
+
+    /// Get the last attribute of the type
+    pub fn attribute(&self, identifier: Id) -> Option<&Attribute> {
+        self.index
+            .get(&identifier.into())
+            .and_then(|positions| positions.last())
+            .map(|position| &self.attributes[*position])
+    }
+
+    /// Get the value of the last attribute of the type, None if missing
+    pub fn get<T: FromAttribute>(&self, identifier: Id) -> Result<Option<T>> {
+        self.attribute(identifier)
+            .map(T::from_attribute)
+            .transpose()
+    }
+
+    /// Get the values of all attributes of the type
+    pub fn get_all<T: FromAttribute>(&self, identifier: Id) -> Result<Vec<T>> {
+        match self.index.get(&identifier.into()) {
+            Some(positions) => positions
+                .iter()
+                .map(|position| T::from_attribute(&self.attributes[*position]))
+                .collect(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Get the value of the last attribute of the type, fails with
+    /// [Error::MissingAttribute](../enum.Error.html) if missing
+    pub fn require<T: FromAttribute>(&self, identifier: Id) -> Result<T> {
+        let identifier = identifier.into();
+        match self.get(Id::from(identifier))? {
+            Some(value) => Ok(value),
+            None => Err(Error::MissingAttribute {
+                identifier,
+                name: format!("{:?}", Id::from(identifier)),
+            }),
+        }
+    }
+
+    /// Validate the attributes against the policy
+    ///
+    /// Attribute types without a rule are accepted. Fails with
+    /// [Error::InvalidAttribute](../enum.Error.html) for the first attribute
+    /// breaking its rule.
+    pub fn validate(&self, policy: &[AttributeRule]) -> Result<()> {
+        for attribute in &self.attributes {
+            let identifier = attribute.attribute_type();
+            let rule = policy.iter().find(|rule| rule.identifier == identifier);
+            if let Some(rule) = rule {
+                if !rule.check(attribute) {
+                    return Err(Error::InvalidAttribute {
+                        identifier,
+                        name: format!("{:?}", Id::from(identifier)),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generic::AttributeId;
+
+    fn family_attributes() -> Vec<Attribute> {
+        vec![
+            Attribute::new(AttributeId::FamilyId, 16u16),
+            Attribute::new_string_with_nul(AttributeId::FamilyName, "nlctrl"),
+            Attribute::new(AttributeId::Version, 2u32),
+            Attribute::new(AttributeId::Version, 3u32),
+        ]
+    }
+
+    #[test]
+    fn lookup_attributes() {
+        let set = AttributeSet::<AttributeId>::new(family_attributes());
+        assert_eq!(set.len(), 4);
+        assert!(set.contains(AttributeId::FamilyName));
+        assert!(!set.contains(AttributeId::MaximumAttributes));
+        assert_eq!(set.require::<u16>(AttributeId::FamilyId).unwrap(), 16);
+        assert_eq!(
+            set.get::<String>(AttributeId::FamilyName).unwrap(),
+            Some(String::from("nlctrl"))
+        );
+        assert_eq!(set.get::<u32>(AttributeId::Version).unwrap(), Some(3));
+        assert_eq!(
+            set.get_all::<u32>(AttributeId::Version).unwrap(),
+            vec![2, 3]
+        );
+        assert!(set.get::<u64>(AttributeId::FamilyId).is_err());
+        match set.require::<u32>(AttributeId::HeaderSize) {
+            Err(Error::MissingAttribute { identifier, name }) => {
+                assert_eq!(identifier, 4);
+                assert_eq!(name, "HeaderSize");
+            }
+            _ => panic!("expected a missing attribute error"),
+        }
+    }
+
+    #[test]
+    fn validate_attributes() {
+        let set = AttributeSet::<AttributeId>::new(family_attributes());
+        let policy = [
+            AttributeRule::new(1, AttributeKind::U16),
+            AttributeRule::new(2, AttributeKind::NulString).with_length(1, 16),
+            AttributeRule::new(3, AttributeKind::U32),
+        ];
+        assert!(set.validate(&policy).is_ok());
+        let policy = [AttributeRule::new(2, AttributeKind::NulString).with_length(1, 4)];
+        match set.validate(&policy) {
+            Err(Error::InvalidAttribute { identifier, name }) => {
+                assert_eq!(identifier, 2);
+                assert_eq!(name, "FamilyName");
+            }
+            _ => panic!("expected an invalid attribute error"),
+        }
+        assert!(set
+            .validate(&[AttributeRule::new(3, AttributeKind::U64)])
+            .is_err());
+        assert!(set
+            .validate(&[AttributeRule::new(2, AttributeKind::Nested)])
+            .is_err());
+    }
+}
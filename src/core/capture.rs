@@ -0,0 +1,372 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::message::{Header, Message};
+use crate::core::pack::NativeUnpack;
+use crate::core::Protocol;
+use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
+
+/// pcap link type of netlink captures
+pub const LINKTYPE_NETLINK: u32 = 253;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b2_3c4d;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const PCAP_SNAPLEN: u32 = 1 << 18;
+const PCAP_RECORD_SIZE: usize = 16;
+
+// The cooked header is the Linux SLL header, as produced when capturing on
+// an nlmon device
+const COOKED_HEADER_SIZE: usize = 16;
+const ARPHRD_NETLINK: u16 = 824;
+const PACKET_HOST: u16 = 0;
+const PACKET_OUTGOING: u16 = 4;
+
+/// Direction of a captured datagram
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Received by the socket
+    Incoming,
+    /// Sent by the socket
+    Outgoing,
+}
+
+/// Writes netlink datagrams to a pcap file
+///
+/// The file uses the `LINKTYPE_NETLINK` link type with a cooked header, the
+/// format of captures on an nlmon device, which Wireshark can dissect. Attach
+/// the recorder to a socket with
+/// [Socket::set_recorder](struct.Socket.html#method.set_recorder) to record
+/// every datagram sent and received.
+///
+/// Recording never fails the socket operations, after a write error the
+/// recorder stops writing and the error is returned by `finish`.
+pub struct PcapRecorder {
+    writer: Box<dyn Write + Send>,
+    protocol: u16,
+    error: Option<io::Error>,
+}
+
+impl PcapRecorder {
+    /// Create a recorder writing to the provided writer, for traffic of the
+    /// netlink protocol
+    ///
+    /// The pcap file header is written immediately.
+    pub fn new<W: Write + Send + 'static>(writer: W, protocol: Protocol) -> Result<PcapRecorder> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC.to_ne_bytes());
+        header.extend_from_slice(&PCAP_VERSION_MAJOR.to_ne_bytes());
+        header.extend_from_slice(&PCAP_VERSION_MINOR.to_ne_bytes());
+        header.extend_from_slice(&0i32.to_ne_bytes());
+        header.extend_from_slice(&0u32.to_ne_bytes());
+        header.extend_from_slice(&PCAP_SNAPLEN.to_ne_bytes());
+        header.extend_from_slice(&LINKTYPE_NETLINK.to_ne_bytes());
+        writer.write_all(&header)?;
+        Ok(PcapRecorder {
+            writer,
+            protocol: i32::from(protocol) as u16,
+            error: None,
+        })
+    }
+
+    /// Create a recorder writing to a new file at the path
+    pub fn create<P: AsRef<Path>>(path: P, protocol: Protocol) -> Result<PcapRecorder> {
+        let file = File::create(path)?;
+        PcapRecorder::new(BufWriter::new(file), protocol)
+    }
+
+    /// Record a datagram made up of the provided buffers
+    pub fn record(&mut self, direction: Direction, buffers: &[&[u8]]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.write_record(direction, buffers) {
+            self.error = Some(err);
+        }
+    }
+
+    fn write_record(&mut self, direction: Direction, buffers: &[&[u8]]) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let size: usize = buffers.iter().map(|buffer| buffer.len()).sum();
+        let length = (COOKED_HEADER_SIZE + size) as u32;
+        let captured = length.min(PCAP_SNAPLEN);
+        let packet_type = match direction {
+            Direction::Incoming => PACKET_HOST,
+            Direction::Outgoing => PACKET_OUTGOING,
+        };
+
+        let mut record = Vec::with_capacity(PCAP_RECORD_SIZE + captured as usize);
+        record.extend_from_slice(&(timestamp.as_secs() as u32).to_ne_bytes());
+        record.extend_from_slice(&timestamp.subsec_micros().to_ne_bytes());
+        record.extend_from_slice(&captured.to_ne_bytes());
+        record.extend_from_slice(&length.to_ne_bytes());
+        // The cooked header is in network byte order, the netlink messages
+        // are in host byte order
+        record.extend_from_slice(&packet_type.to_be_bytes());
+        record.extend_from_slice(&ARPHRD_NETLINK.to_be_bytes());
+        record.extend_from_slice(&[0u8; 10]);
+        record.extend_from_slice(&self.protocol.to_be_bytes());
+        for buffer in buffers {
+            record.extend_from_slice(buffer);
+        }
+        record.truncate(PCAP_RECORD_SIZE + captured as usize);
+        self.writer.write_all(&record)
+    }
+
+    /// Flush the recorded datagrams to the writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush the recorder, returns the first error which occurred while
+    /// recording
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        self.flush()
+    }
+}
+
+/// A datagram read from a pcap file
+#[derive(Clone, Debug)]
+pub struct PcapPacket {
+    /// Capture time, since the Unix epoch
+    pub timestamp: Duration,
+    /// Direction of the datagram
+    pub direction: Direction,
+    /// Netlink protocol of the datagram
+    pub protocol: u16,
+    /// The captured datagram
+    pub data: Vec<u8>,
+}
+
+impl PcapPacket {
+    /// Unpack the messages of the datagram
+    ///
+    /// Control messages, such as errors and the end of a dump, are returned
+    /// as messages too.
+    pub fn messages(&self) -> Result<Vec<Message>> {
+        let mut messages = vec![];
+        let mut data = &self.data[..];
+        while !data.is_empty() {
+            let (used, header) = Header::unpack_with_size(data)?;
+            // The length is read from the file, check it before allocating
+            if header.length() < used || header.length() > data.len() {
+                return Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into());
+            }
+            // The last message of a datagram may lack the padding
+            let size = header.aligned_length().min(data.len());
+            let mut message = vec![0u8; header.aligned_length()];
+            message[..size].copy_from_slice(&data[..size]);
+            let (_, message) = Message::unpack(&message[used..], header)?;
+            messages.push(message);
+            data = &data[size..];
+        }
+        Ok(messages)
+    }
+}
+
+/// Reads netlink datagrams from a pcap file
+///
+/// Reads files written by [PcapRecorder](struct.PcapRecorder.html), or
+/// captured on an nlmon device. The iterator yields the datagrams in the
+/// order of the file.
+pub struct PcapReader<R: Read> {
+    reader: R,
+    swapped: bool,
+    nanoseconds: bool,
+    failed: bool,
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Open a pcap file at the path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PcapReader<BufReader<File>>> {
+        let file = File::open(path)?;
+        PcapReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Create a reader, reading the pcap file header from the reader
+    ///
+    /// Fails if the file is not a pcap file with the `LINKTYPE_NETLINK` link
+    /// type.
+    pub fn new(mut reader: R) -> Result<PcapReader<R>> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+        let magic = u32::unpack_unchecked(&header);
+        let (swapped, nanoseconds) = match magic {
+            PCAP_MAGIC => (false, false),
+            PCAP_MAGIC_NANOSECONDS => (false, true),
+            _ if magic.swap_bytes() == PCAP_MAGIC => (true, false),
+            _ if magic.swap_bytes() == PCAP_MAGIC_NANOSECONDS => (true, true),
+            _ => return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into()),
+        };
+        let pcap = PcapReader {
+            reader,
+            swapped,
+            nanoseconds,
+            failed: false,
+        };
+        if pcap.read_u32(&header[20..]) != LINKTYPE_NETLINK {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into());
+        }
+        Ok(pcap)
+    }
+
+    fn read_u32(&self, data: &[u8]) -> u32 {
+        let value = u32::unpack_unchecked(data);
+        if self.swapped {
+            value.swap_bytes()
+        } else {
+            value
+        }
+    }
+
+    /// Read the next datagram, returns None at the end of the file
+    pub fn read_packet(&mut self) -> Result<Option<PcapPacket>> {
+        let mut record = [0u8; PCAP_RECORD_SIZE];
+        match self.reader.read_exact(&mut record) {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let seconds = self.read_u32(&record[0..]) as u64;
+        let fraction = self.read_u32(&record[4..]);
+        let captured = self.read_u32(&record[8..]) as usize;
+        if captured < COOKED_HEADER_SIZE || captured > PCAP_SNAPLEN as usize {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into());
+        }
+        let mut data = vec![0u8; captured];
+        self.reader.read_exact(&mut data)?;
+
+        let packet_type = u16::from_be_bytes([data[0], data[1]]);
+        let hardware_type = u16::from_be_bytes([data[2], data[3]]);
+        if hardware_type != ARPHRD_NETLINK {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into());
+        }
+        let protocol = u16::from_be_bytes([data[14], data[15]]);
+        let timestamp = if self.nanoseconds {
+            Duration::new(seconds, fraction)
+        } else {
+            Duration::new(seconds, fraction.saturating_mul(1000))
+        };
+        let direction = if packet_type == PACKET_OUTGOING {
+            Direction::Outgoing
+        } else {
+            Direction::Incoming
+        };
+        Ok(Some(PcapPacket {
+            timestamp,
+            direction,
+            protocol,
+            data: data.split_off(COOKED_HEADER_SIZE),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<PcapPacket>;
+
+    fn next(&mut self) -> Option<Result<PcapPacket>> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_packet();
+        // The position in the file is lost after an error
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::pack::NativePack;
+    use crate::core::transport::{ack_message, MemoryTransport};
+    use crate::core::{MessageMode, Socket};
+    use crate::generic;
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("netlink-{}.pcap", std::process::id()));
+        let transport = MemoryTransport::new();
+        transport.set_handler(|request| vec![ack_message(&Header::unpack(request).unwrap())]);
+        let mut socket = Socket::with_transport(transport, 300);
+        let recorder = PcapRecorder::create(&path, Protocol::Generic).unwrap();
+        assert!(socket.set_recorder(Some(recorder)).is_none());
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::Acknowledge);
+        assert!(socket.transact(&msg).unwrap().is_empty());
+        socket.set_recorder(None).unwrap().finish().unwrap();
+
+        let packets = PcapReader::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].direction, Direction::Outgoing);
+        assert_eq!(packets[1].direction, Direction::Incoming);
+        assert!(packets.iter().all(|p| p.protocol == 16));
+        let request = packets[0].messages().unwrap();
+        assert_eq!(request.len(), 1);
+        assert_eq!(request[0].header.identifier, 0x10);
+        assert_eq!(
+            (request[0].header.sequence, request[0].header.pid),
+            (1, 300)
+        );
+        let reply = packets[1].messages().unwrap();
+        assert_eq!(reply.len(), 1);
+        assert_eq!(reply[0].header.identifier, 2);
+        assert_eq!(reply[0].header.sequence, 1);
+    }
+
+    #[test]
+    fn reject_other_link_types() {
+        let mut header = vec![];
+        header.extend_from_slice(&PCAP_MAGIC.swap_bytes().to_ne_bytes());
+        header.extend_from_slice(&PCAP_VERSION_MAJOR.swap_bytes().to_ne_bytes());
+        header.extend_from_slice(&PCAP_VERSION_MINOR.swap_bytes().to_ne_bytes());
+        header.extend_from_slice(&[0u8; 8]);
+        header.extend_from_slice(&PCAP_SNAPLEN.swap_bytes().to_ne_bytes());
+        header.extend_from_slice(&1u32.swap_bytes().to_ne_bytes());
+        assert!(PcapReader::new(&header[..]).is_err());
+        let length = header.len();
+        header[length - 4..].copy_from_slice(&LINKTYPE_NETLINK.swap_bytes().to_ne_bytes());
+        let mut reader = PcapReader::new(&header[..]).unwrap();
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn reject_truncated_messages() {
+        let mut data = vec![0u8; Header::HEADER_SIZE];
+        let header = Header {
+            length: 24,
+            identifier: 2,
+            flags: 0,
+            sequence: 1,
+            pid: 0,
+        };
+        header.pack(&mut data).unwrap();
+        let mut packet = PcapPacket {
+            timestamp: Duration::ZERO,
+            direction: Direction::Incoming,
+            protocol: 0,
+            data,
+        };
+        // Truncated record, the message is longer than the datagram
+        assert!(packet.messages().is_err());
+        // Oversized length, which must not be allocated
+        u32::MAX.pack(&mut packet.data).unwrap();
+        assert!(packet.messages().is_err());
+        (Header::HEADER_SIZE as u32).pack(&mut packet.data).unwrap();
+        assert_eq!(packet.messages().unwrap().len(), 1);
+    }
+}
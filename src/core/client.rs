@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::core::message::{Message, Messages};
+use crate::core::socket::{ReceiveMode, Reply, SendMessage, Socket};
+use crate::core::system;
+use crate::core::Protocol;
+use crate::errors::{Error, Result};
+
+/// Interval at which the reader thread checks if the client has been dropped
+const READER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reply for a request, None marks the end of the replies
+type ReplySender = Sender<Result<Option<Message>>>;
+
+struct State {
+    socket: Socket,
+    requests: HashMap<u32, ReplySender>,
+    subscribers: Vec<Sender<Result<Message>>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    stop: AtomicBool,
+}
+
+/// Netlink client which can be shared between threads
+///
+/// Owns a [Socket](struct.Socket.html) which is read by a background thread.
+/// Replies are dispatched to the requesting thread by sequence number, so
+/// several threads can have requests in flight at the same time. Share the
+/// client using an `Arc`. Note that the kernel only runs one dump at a time
+/// per socket, a concurrent dump fails with `EBUSY`.
+///
+/// Multi-cast notifications are delivered to every subscriber, see
+/// `subscribe`.
+pub struct Client {
+    shared: Arc<Shared>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Client {
+    /// Create a new Client
+    pub fn new(protocol: Protocol) -> Result<Client> {
+        Client::from_socket(Socket::new(protocol)?)
+    }
+
+    /// Create a new Client from a Socket, the socket is switched into
+    /// non-blocking mode
+    pub fn from_socket(mut socket: Socket) -> Result<Client> {
+        socket.set_receive_mode(ReceiveMode::NonBlocking)?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                socket,
+                requests: HashMap::new(),
+                subscribers: vec![],
+            }),
+            stop: AtomicBool::new(false),
+        });
+        let reader_shared = shared.clone();
+        let reader = thread::Builder::new()
+            .name("netlink-client".into())
+            .spawn(move || reader_shared.run())?;
+        Ok(Client {
+            shared,
+            reader: Some(reader),
+        })
+    }
+
+    /// Send the provided message and wait for the replies
+    ///
+    /// Returns once the request has been completed, that is after the reply,
+    /// the acknowledge or the end of the dump depending on the message flags.
+    /// A pending request fails with an
+    /// [Error::Overrun](enum.Error.html) if replies were lost, use
+    /// `request_timeout` to limit the time waited for the replies.
+    pub fn request<S: SendMessage>(&self, message: &S) -> Result<Messages> {
+        self.request_until(message, None)
+    }
+
+    /// Send the provided message and wait for the replies, at most for the
+    /// provided duration
+    ///
+    /// An expired timeout is reported as an `io::ErrorKind::TimedOut` error.
+    /// The request is then forgotten, replies received later are delivered
+    /// to the subscribers as notifications. See `request`.
+    pub fn request_timeout<S: SendMessage>(
+        &self,
+        message: &S,
+        timeout: Duration,
+    ) -> Result<Messages> {
+        self.request_until(message, Some(Instant::now() + timeout))
+    }
+
+    fn request_until<S: SendMessage>(
+        &self,
+        message: &S,
+        deadline: Option<Instant>,
+    ) -> Result<Messages> {
+        let (sender, receiver) = channel();
+        let sequence = {
+            let mut state = self.shared.lock();
+            let sequence = state.socket.send_message(message)?;
+            state.requests.insert(sequence, sender);
+            sequence
+        };
+        let mut messages = vec![];
+        loop {
+            let reply = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(reply) => reply,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let mut state = self.shared.lock();
+                            if state.requests.remove(&sequence).is_some() {
+                                state.socket.forget(sequence);
+                            }
+                            return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(io::Error::from(io::ErrorKind::BrokenPipe).into())
+                        }
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => return Err(io::Error::from(io::ErrorKind::BrokenPipe).into()),
+                },
+            };
+            match reply {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => return Ok(messages),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Subscribe to multi-cast notifications
+    ///
+    /// Every subscriber receives all notifications received after
+    /// subscribing. An [Error::Overrun](enum.Error.html) is delivered if
+    /// notifications were lost. Dropping the receiver ends the subscription.
+    pub fn subscribe(&self) -> Receiver<Result<Message>> {
+        let (sender, receiver) = channel();
+        self.shared.lock().subscribers.push(sender);
+        receiver
+    }
+
+    /// Subscribe to the multi-cast group provided
+    pub fn multicast_group_subscribe(&self, group: u32) -> Result<()> {
+        self.shared.lock().socket.multicast_group_subscribe(group)
+    }
+
+    /// Unsubscribe from the multi-cast group provided
+    pub fn multicast_group_unsubscribe(&self, group: u32) -> Result<()> {
+        self.shared.lock().socket.multicast_group_unsubscribe(group)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn run(&self) {
+        let fd = self.lock().socket.as_raw_fd();
+        while !self.stop.load(Ordering::Relaxed) {
+            if let Err(err) = system::poll_read(fd, Some(READER_INTERVAL)) {
+                if err.kind() != io::ErrorKind::Interrupted {
+                    self.lock().fail(&err.into());
+                    thread::sleep(READER_INTERVAL);
+                }
+                continue;
+            }
+            let mut state = self.lock();
+            loop {
+                match state.socket.receive_once() {
+                    Ok(true) => state.dispatch(),
+                    Ok(false) => break,
+                    Err(err) => {
+                        state.dispatch();
+                        state.fail(&err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    /// Forward received replies and notifications
+    fn dispatch(&mut self) {
+        let socket = &mut self.socket;
+        self.requests.retain(|&sequence, sender| loop {
+            let (reply, done) = match socket.next_reply(sequence) {
+                Ok(Reply::Message(message)) => (Ok(Some(message)), false),
+                Ok(Reply::Done) => (Ok(None), true),
+                Ok(Reply::Pending) => return true,
+                Err(err) => (Err(err), true),
+            };
+            if sender.send(reply).is_err() {
+                // The requesting thread is gone
+                socket.cancel(sequence);
+                return false;
+            }
+            if done {
+                return false;
+            }
+        });
+        while let Some(message) = self.socket.next_notification() {
+            self.subscribers
+                .retain(|subscriber| subscriber.send(Ok(message.clone())).is_ok());
+        }
+    }
+
+    /// Report an error not caused by a specific request
+    fn fail(&mut self, err: &Error) {
+        if let Error::Overrun = *err {
+            self.subscribers
+                .retain(|subscriber| subscriber.send(Err(Error::Overrun)).is_ok());
+        }
+        // The replies of pending requests may have been lost
+        for (sequence, sender) in self.requests.drain() {
+            self.socket.forget(sequence);
+            let _ = sender.send(Err(copy_error(err)));
+        }
+    }
+}
+
+fn copy_error(err: &Error) -> Error {
+    match *err {
+        Error::ExtendedAck(ref ack) => Error::ExtendedAck(ack.clone()),
+        Error::Overrun => Error::Overrun,
+        Error::DumpInterrupted => Error::DumpInterrupted,
+        _ => match err.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code).into(),
+            None => io::Error::other(err.to_string()).into(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Attribute, ExtendedAck, MessageMode};
+    use crate::{generic, route};
+
+    #[test]
+    fn concurrent_requests() {
+        let client = Arc::new(Client::new(Protocol::Generic).unwrap());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let mut msg = generic::Message::new(
+                        generic::FamilyId::Control,
+                        generic::Command::GetFamily,
+                        MessageMode::Acknowledge,
+                    );
+                    msg.append_attribute(Attribute::new_string_with_nul(
+                        generic::AttributeId::FamilyName,
+                        "nlctrl",
+                    ));
+                    client.request(&msg).unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn request_timeout() {
+        // The peer never replies
+        let peer = Socket::new(Protocol::Usersock).unwrap();
+        let mut socket = Socket::new(Protocol::Usersock).unwrap();
+        socket.connect(peer.port_id()).unwrap();
+        let client = Client::from_socket(socket).unwrap();
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        match client.request_timeout(&msg, Duration::from_millis(50)) {
+            Err(Error::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+            _ => panic!("expected a timeout"),
+        }
+        let state = client.shared.lock();
+        assert!(state.requests.is_empty());
+        assert!(!state.socket.is_pending());
+    }
+
+    #[test]
+    fn overrun_fails_requests() {
+        let mut state = State {
+            socket: Socket::new(Protocol::Usersock).unwrap(),
+            requests: HashMap::new(),
+            subscribers: vec![],
+        };
+        let (sender, receiver) = channel();
+        state.requests.insert(1, sender);
+        state.fail(&Error::Overrun);
+        assert!(state.requests.is_empty());
+        match receiver.recv().unwrap() {
+            Err(Error::Overrun) => {}
+            _ => panic!("expected an overrun"),
+        }
+    }
+
+    #[test]
+    fn copy_extended_ack() {
+        let ack = ExtendedAck {
+            code: -libc::EINVAL,
+            message: Some("invalid attribute".into()),
+            offset: Some(20),
+            ..Default::default()
+        };
+        match copy_error(&Error::ExtendedAck(Box::new(ack.clone()))) {
+            Error::ExtendedAck(copy) => assert_eq!(*copy, ack),
+            _ => panic!("expected an extended acknowledge"),
+        }
+    }
+
+    #[test]
+    fn notification_subscribers() {
+        let port = 0x7100_0000 + std::process::id();
+        let client =
+            Client::from_socket(Socket::new_with_port(Protocol::Usersock, port).unwrap()).unwrap();
+        let first = client.subscribe();
+        let second = client.subscribe();
+        let mut sender = Socket::new(Protocol::Usersock).unwrap();
+        sender.connect(port).unwrap();
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        sender.send_message(&msg).unwrap();
+        for subscriber in [first, second] {
+            let message = subscriber
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .unwrap();
+            assert_eq!(message.header.pid, sender.port_id());
+        }
+    }
+}
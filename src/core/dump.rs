@@ -0,0 +1,90 @@
+use crate::core::message::{Message, MessageFlags};
+use crate::core::socket::{Reply, Socket};
+use crate::core::transport::{NetlinkTransport, Transport};
+use crate::errors::Result;
+
+/// Iterator over the replies of a dump request
+///
+/// Created by [Socket::dump](struct.Socket.html#method.dump). Messages are
+/// received lazily, one receive buffer at a time, so a large dump does not
+/// need to be held in memory. The iteration ends after `NLMSG_DONE`, an
+/// error reported by the kernel mid-dump is yielded as the last item. If the
+/// dumped table changed during the dump the last item is
+/// [Error::DumpInterrupted](enum.Error.html).
+///
+/// Dropping the iterator before the end cancels the dump, the remaining
+/// replies are received and discarded.
+pub struct DumpIter<'a, T: Transport = NetlinkTransport> {
+    socket: &'a mut Socket<T>,
+    sequence: u32,
+    finished: bool,
+    filtered: bool,
+}
+
+impl<'a, T: Transport> DumpIter<'a, T> {
+    pub(crate) fn new(socket: &'a mut Socket<T>, sequence: u32) -> DumpIter<'a, T> {
+        DumpIter {
+            socket,
+            sequence,
+            finished: false,
+            filtered: false,
+        }
+    }
+
+    /// Get the sequence number of the dump request
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Check if the kernel filtered the dump, `NLM_F_DUMP_FILTERED`
+    ///
+    /// Set once a filtered reply has been received, kernels without strict
+    /// checking ignore the filter attributes of a request.
+    pub fn is_filtered(&self) -> bool {
+        self.filtered
+    }
+}
+
+impl<'a, T: Transport> Iterator for DumpIter<'a, T> {
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Result<Message>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let result = match self.socket.next_reply(self.sequence) {
+                Ok(Reply::Message(message)) => {
+                    let flags = message.header.flags();
+                    self.filtered |= flags.contains(MessageFlags::DUMP_FILTERED);
+                    return Some(Ok(message));
+                }
+                Ok(Reply::Pending) => self.socket.receive_or_wait(),
+                Ok(Reply::Done) => {
+                    self.finished = true;
+                    return None;
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                self.finished = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl<'a, T: Transport> Drop for DumpIter<'a, T> {
+    fn drop(&mut self) {
+        if !self.socket.is_outstanding(self.sequence) {
+            return;
+        }
+        self.socket.cancel(self.sequence);
+        // The kernel only runs one dump at a time per socket, drain the rest
+        while self.socket.is_outstanding(self.sequence) {
+            if self.socket.receive_or_wait().is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
+
+const BPF_LD: u16 = 0x00;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+const BPF_ABS: u16 = 0x20;
+const BPF_JEQ: u16 = 0x10;
+const BPF_K: u16 = 0x00;
+
+/// Offset of the message type in the netlink message header
+const NLMSG_TYPE_OFFSET: u32 = 4;
+
+/// Classic BPF instruction
+///
+/// Same layout as `struct sock_filter`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FilterInstruction {
+    /// Operation code
+    pub code: u16,
+    /// Jump offset if true
+    pub jt: u8,
+    /// Jump offset if false
+    pub jf: u8,
+    /// Operation argument
+    pub k: u32,
+}
+
+impl FilterInstruction {
+    /// Create a new instruction
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> FilterInstruction {
+        FilterInstruction { code, jt, jf, k }
+    }
+}
+
+/// Classic BPF socket filter
+///
+/// The filter runs in the kernel for every datagram delivered to the socket,
+/// datagrams it drops are never received. Attach it with
+/// [Socket::attach_filter](struct.Socket.html#method.attach_filter), use
+/// [FilterBuilder](struct.FilterBuilder.html) for the common netlink filters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    instructions: Vec<FilterInstruction>,
+}
+
+impl Filter {
+    /// Create a filter from a BPF program
+    pub fn from_instructions(instructions: Vec<FilterInstruction>) -> Filter {
+        Filter { instructions }
+    }
+
+    /// Get the BPF program
+    pub fn instructions(&self) -> &[FilterInstruction] {
+        &self.instructions
+    }
+}
+
+/// Load of a value at an offset and its expected value, as loaded by BPF
+#[derive(Clone, Copy)]
+struct Compare {
+    size: u16,
+    offset: u32,
+    value: u32,
+}
+
+/// Builder for filters accepting datagrams which match any of the rules
+///
+/// A rule matches the first message of a datagram, the kernel delivers
+/// notifications in datagrams of their own. Offsets are from the start of
+/// the message header, a datagram too short for a rule does not match it.
+/// Without rules every datagram is dropped.
+///
+/// Do not filter out `NLMSG_ERROR` or `NLMSG_DONE` messages on a socket
+/// used for requests.
+///
+/// ```
+/// use netlink_rust::core::FilterBuilder;
+///
+/// // Accept RTM_NEWLINK and RTM_DELLINK notifications
+/// let filter = FilterBuilder::new()
+///     .message_type(16)
+///     .message_type(17)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct FilterBuilder {
+    rules: Vec<Vec<Compare>>,
+}
+
+impl FilterBuilder {
+    /// Create a builder without any rules
+    pub fn new() -> FilterBuilder {
+        FilterBuilder::default()
+    }
+
+    /// Accept messages of the message type
+    pub fn message_type(self, identifier: u16) -> FilterBuilder {
+        self.value(NLMSG_TYPE_OFFSET, &identifier.to_ne_bytes())
+    }
+
+    /// Accept messages with the u8 value at the offset
+    pub fn value_u8(self, offset: u32, value: u8) -> FilterBuilder {
+        self.value(offset, &[value])
+    }
+
+    /// Accept messages with the u16 value, in native byte order, at the
+    /// offset
+    pub fn value_u16(self, offset: u32, value: u16) -> FilterBuilder {
+        self.value(offset, &value.to_ne_bytes())
+    }
+
+    /// Accept messages with the u32 value, in native byte order, at the
+    /// offset
+    pub fn value_u32(self, offset: u32, value: u32) -> FilterBuilder {
+        self.value(offset, &value.to_ne_bytes())
+    }
+
+    /// Accept messages with the bytes at the offset
+    ///
+    /// For example the attribute value at a fixed offset, or the start of a
+    /// uevent such as `add@`.
+    pub fn value(mut self, offset: u32, bytes: &[u8]) -> FilterBuilder {
+        let mut rule = vec![];
+        let mut offset = offset;
+        let mut bytes = bytes;
+        // BPF loads are in network byte order, compare the bytes as loaded
+        while !bytes.is_empty() {
+            let (size, length) = match bytes.len() {
+                1 => (BPF_B, 1),
+                2 | 3 => (BPF_H, 2),
+                _ => (BPF_W, 4),
+            };
+            let value = bytes[..length]
+                .iter()
+                .fold(0u32, |value, byte| value << 8 | *byte as u32);
+            rule.push(Compare {
+                size,
+                offset,
+                value,
+            });
+            offset += length as u32;
+            bytes = &bytes[length..];
+        }
+        self.rules.push(rule);
+        self
+    }
+
+    /// Build the filter
+    ///
+    /// Fails if a rule is too long to jump over.
+    pub fn build(self) -> Result<Filter> {
+        let mut instructions = vec![];
+        for rule in &self.rules {
+            // Each compare is a load and a jump, skip to the next rule if
+            // the value differs
+            let length = rule.len() * 2 + 1;
+            if length > u8::MAX as usize {
+                return Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into());
+            }
+            for (index, compare) in rule.iter().enumerate() {
+                let code = BPF_LD | compare.size | BPF_ABS;
+                instructions.push(FilterInstruction::new(code, 0, 0, compare.offset));
+                let skip = (length - index * 2 - 2) as u8;
+                let code = BPF_JMP | BPF_JEQ | BPF_K;
+                instructions.push(FilterInstruction::new(code, 0, skip, compare.value));
+            }
+            instructions.push(FilterInstruction::new(BPF_RET | BPF_K, 0, 0, u32::MAX));
+        }
+        instructions.push(FilterInstruction::new(BPF_RET | BPF_K, 0, 0, 0));
+        Ok(Filter::from_instructions(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_filter() {
+        let filter = FilterBuilder::new()
+            .message_type(16)
+            .value(16, b"add@x")
+            .build()
+            .unwrap();
+        let load = |size, offset| FilterInstruction::new(BPF_LD | size | BPF_ABS, 0, 0, offset);
+        let jump = |skip, value| FilterInstruction::new(BPF_JMP | BPF_JEQ | BPF_K, 0, skip, value);
+        let accept = FilterInstruction::new(BPF_RET | BPF_K, 0, 0, u32::MAX);
+        let drop = FilterInstruction::new(BPF_RET | BPF_K, 0, 0, 0);
+        let message_type = u16::from_be_bytes(16u16.to_ne_bytes()) as u32;
+        assert_eq!(
+            filter.instructions(),
+            &[
+                load(BPF_H, 4),
+                jump(1, message_type),
+                accept,
+                load(BPF_W, 16),
+                jump(3, 0x6164_6440),
+                load(BPF_B, 20),
+                jump(1, 0x78),
+                accept,
+                drop,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_filter() {
+        let filter = FilterBuilder::new().build().unwrap();
+        assert_eq!(
+            filter.instructions(),
+            &[FilterInstruction::new(BPF_RET | BPF_K, 0, 0, 0)]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::errors::{Error, NetlinkError, NetlinkErrorKind, Result};
+
+/// IP address with a prefix length, such as `192.168.1.0/24`
+///
+/// Route and address messages carry the prefix length in the message
+/// header and the address as an attribute, combine them with `new`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct IpPrefix {
+    address: IpAddr,
+    length: u8,
+}
+
+impl IpPrefix {
+    /// Create a new prefix, fails if the length is longer than the address
+    pub fn new(address: IpAddr, length: u8) -> Result<IpPrefix> {
+        if length > maximum_length(&address) {
+            return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into());
+        }
+        Ok(IpPrefix { address, length })
+    }
+
+    /// Get the address
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// Get the prefix length in bits
+    pub fn prefix_length(&self) -> u8 {
+        self.length
+    }
+
+    /// Get the network address, the address with the host bits cleared
+    pub fn network(&self) -> IpAddr {
+        match self.address {
+            IpAddr::V4(address) => {
+                let mask = u32::MAX.checked_shl(32 - self.length as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+            }
+            IpAddr::V6(address) => {
+                let mask = u128::MAX.checked_shl(128 - self.length as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+            }
+        }
+    }
+
+    /// Check if the address is within the prefix
+    pub fn contains(&self, address: IpAddr) -> bool {
+        if self.address.is_ipv4() != address.is_ipv4() {
+            return false;
+        }
+        IpPrefix {
+            address,
+            length: self.length,
+        }
+        .network()
+            == self.network()
+    }
+}
+
+fn maximum_length(address: &IpAddr) -> u8 {
+    match *address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+impl fmt::Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.length)
+    }
+}
+
+impl From<IpAddr> for IpPrefix {
+    /// Prefix of a single host
+    fn from(address: IpAddr) -> IpPrefix {
+        let length = maximum_length(&address);
+        IpPrefix { address, length }
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = Error;
+
+    /// Parse `address/length`, or an address without length for a host
+    fn from_str(value: &str) -> Result<IpPrefix> {
+        let invalid = || Error::from(NetlinkError::new(NetlinkErrorKind::InvalidValue));
+        let (address, length) = match value.split_once('/') {
+            Some((address, length)) => (address, Some(length)),
+            None => (value, None),
+        };
+        let address = IpAddr::from_str(address).map_err(|_| invalid())?;
+        match length {
+            Some(length) => {
+                let length = u8::from_str(length).map_err(|_| invalid())?;
+                IpPrefix::new(address, length)
+            }
+            None => Ok(IpPrefix::from(address)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_prefix() {
+        let prefix: IpPrefix = "192.168.1.17/24".parse().unwrap();
+        assert_eq!(prefix.address(), IpAddr::from([192, 168, 1, 17]));
+        assert_eq!(prefix.prefix_length(), 24);
+        assert_eq!(prefix.network(), IpAddr::from([192, 168, 1, 0]));
+        assert_eq!(prefix.to_string(), "192.168.1.17/24");
+        assert!(prefix.contains(IpAddr::from([192, 168, 1, 200])));
+        assert!(!prefix.contains(IpAddr::from([192, 168, 2, 1])));
+
+        let prefix: IpPrefix = "2001:db8::1".parse().unwrap();
+        assert_eq!(prefix.prefix_length(), 128);
+        let prefix: IpPrefix = "2001:db8::1/32".parse().unwrap();
+        assert_eq!(prefix.network(), "2001:db8::".parse::<IpAddr>().unwrap());
+        assert!(!prefix.contains(IpAddr::from([32, 1, 13, 184])));
+
+        let prefix: IpPrefix = "10.1.2.3/0".parse().unwrap();
+        assert_eq!(prefix.network(), IpAddr::from([0, 0, 0, 0]));
+        assert!(prefix.contains(IpAddr::from([172, 16, 0, 1])));
+
+        assert!("10.0.0.0/33".parse::<IpPrefix>().is_err());
+        assert!("10.0.0/8".parse::<IpPrefix>().is_err());
+        assert!("10.0.0.0/x".parse::<IpPrefix>().is_err());
+    }
+}
@@ -1,12 +1,19 @@
-use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
+use crate::errors::{Error, NetlinkError, NetlinkErrorKind, Result};
 use bitflags::bitflags;
 use std::fmt;
+use std::io;
 use std::mem::size_of;
 
+use crate::core::acknowledge::ExtendedAck;
+use crate::core::attribute::AttributeIter;
 use crate::core::pack::{NativePack, NativeUnpack};
 
 bitflags! {
     /// Message flags
+    ///
+    /// The meaning of the upper byte depends on the kind of message, the
+    /// flags of get requests, new requests, delete requests and
+    /// acknowledge messages share the same values.
     #[derive(Clone, Copy, PartialEq, PartialOrd)]
     pub struct MessageFlags: u16 {
         /// Request message
@@ -15,14 +22,48 @@ bitflags! {
         const MULTIPART   = 0x0002;
         /// Acknowledge message
         const ACKNOWLEDGE = 0x0004;
+        /// Echo the request back, as with notifications
+        const ECHO        = 0x0008;
+        /// The dump was interrupted by a change of the dumped table, the
+        /// replies are inconsistent
+        const DUMP_INTERRUPTED = 0x0010;
+        /// The kernel filtered the dump as requested
+        const DUMP_FILTERED = 0x0020;
+
+        /// Get request, return the whole table
+        const ROOT        = 0x0100;
+        /// Get request, return all matching entries
+        const MATCH       = 0x0200;
+        /// Get request, return an atomic snapshot of the table
+        const ATOMIC      = 0x0400;
         /// Dump message
         const DUMP        = 0x0100 | 0x0200;
+
+        /// New request, replace the existing entry
+        const REPLACE     = 0x0100;
+        /// New request, fail if the entry already exists
+        const EXCL        = 0x0200;
+        /// New request, create the entry if it does not exist
+        const CREATE      = 0x0400;
+        /// New request, add the entry to the end of the list
+        const APPEND      = 0x0800;
+
+        /// Delete request, do not delete recursively
+        const NONREC      = 0x0100;
+
+        /// Acknowledge message, the original message was not included
+        const CAPPED      = 0x0100;
+        /// Acknowledge message, extended acknowledge attributes are included
+        const ACK_TLVS    = 0x0200;
     }
 }
 
 /// Message mode
 ///
-/// Flags wich describes how the messages will be hadled
+/// Flags wich describes how the messages will be hadled. The create, replace
+/// and append modes are for new requests, such as `RTM_NEWROUTE`, and are
+/// acknowledged. Add `MessageFlags::ECHO` to the flags of a mode to receive
+/// the resulting entry as reply.
 #[derive(PartialEq)]
 pub enum MessageMode {
     /// No special flags
@@ -31,10 +72,19 @@ pub enum MessageMode {
     Acknowledge,
     /// Dump message
     Dump,
+    /// Create a new entry, fail if it already exists
+    Create,
+    /// Create a new entry, or replace the existing entry
+    Replace,
+    /// Create a new entry, adding it to the end of the list
+    Append,
 }
 
 impl From<MessageFlags> for MessageMode {
     fn from(value: MessageFlags) -> MessageMode {
+        // Like the kernel, either ROOT or MATCH makes a get request a dump.
+        // The flags share their values with REPLACE, EXCL and NONREC of new
+        // and delete requests, which therefore are reported as dumps too
         if value.intersects(MessageFlags::DUMP) {
             MessageMode::Dump
         } else if value.intersects(MessageFlags::ACKNOWLEDGE) {
@@ -52,6 +102,15 @@ impl From<MessageMode> for MessageFlags {
             MessageMode::None => flags,
             MessageMode::Acknowledge => flags | MessageFlags::ACKNOWLEDGE,
             MessageMode::Dump => flags | MessageFlags::DUMP,
+            MessageMode::Create => {
+                flags | MessageFlags::ACKNOWLEDGE | MessageFlags::CREATE | MessageFlags::EXCL
+            }
+            MessageMode::Replace => {
+                flags | MessageFlags::ACKNOWLEDGE | MessageFlags::CREATE | MessageFlags::REPLACE
+            }
+            MessageMode::Append => {
+                flags | MessageFlags::ACKNOWLEDGE | MessageFlags::CREATE | MessageFlags::APPEND
+            }
         }
     }
 }
@@ -85,6 +144,7 @@ pub(crate) fn netlink_padding(len: usize) -> usize {
 /// by the length field.
 ///
 #[repr(C)]
+#[derive(Clone)]
 pub struct Header {
     /// Message length
     pub length: u32,
@@ -99,7 +159,7 @@ pub struct Header {
 }
 
 impl Header {
-    const HEADER_SIZE: usize = 16;
+    pub(crate) const HEADER_SIZE: usize = 16;
 
     /// Returns the length including the header
     pub fn length(&self) -> usize {
@@ -190,10 +250,14 @@ impl NativeUnpack for Header {
 /// Header is the message header, See [Header](struct.Header.html).
 /// The error code is an errno number reported by the kernel.
 /// The original header is the header of the message that caused this error.
+/// Unless `NLM_F_CAPPED` is set, the original header is followed by the
+/// original message data. When `NLM_F_ACK_TLVS` is set, extended acknowledge
+/// attributes follow, see [ExtendedAck](struct.ExtendedAck.html).
 pub(crate) struct ErrorMessage {
     pub header: Header,
     pub code: i32,
     pub original_header: Header,
+    pub ack: Option<ExtendedAck>,
 }
 
 impl ErrorMessage {
@@ -204,15 +268,68 @@ impl ErrorMessage {
         }
         let code = i32::unpack_unchecked(data);
         let (_, original) = Header::unpack_with_size(&data[4..])?;
+        let mut used = size;
+        let mut ack = None;
+        if header.flags().contains(MessageFlags::ACK_TLVS) {
+            let end = header.data_length().min(data.len());
+            let original_end = if header.flags().contains(MessageFlags::CAPPED) {
+                size
+            } else {
+                4 + original.aligned_length()
+            };
+            if original_end <= end {
+                let original_data = if original_end > size {
+                    Some(&data[4..original_end])
+                } else {
+                    None
+                };
+                let extended = ExtendedAck::unpack(code, &data[original_end..end], original_data);
+                if !extended.is_empty() {
+                    ack = Some(extended);
+                }
+                used = end;
+            }
+        }
         Ok((
-            size,
+            used,
             ErrorMessage {
                 header: header,
                 code: code,
                 original_header: original,
+                ack,
             },
         ))
     }
+
+    /// Get the error reported by the message, None if it is an acknowledge
+    pub fn error(self) -> Option<Error> {
+        acknowledge_error(self.code, self.ack)
+    }
+}
+
+/// Unpack the error code of a done message, with the optional extended
+/// acknowledge attributes following it
+pub(crate) fn unpack_done(data: &[u8], header: &Header) -> Option<Error> {
+    let data = &data[..header.data_length().min(data.len())];
+    let code = i32::unpack(data).unwrap_or(0);
+    let mut ack = None;
+    if header.flags().contains(MessageFlags::ACK_TLVS) && data.len() > 4 {
+        let extended = ExtendedAck::unpack(code, &data[4..], None);
+        if !extended.is_empty() {
+            ack = Some(extended);
+        }
+    }
+    acknowledge_error(code, ack)
+}
+
+fn acknowledge_error(code: i32, ack: Option<ExtendedAck>) -> Option<Error> {
+    if code >= 0 {
+        return None;
+    }
+    match ack {
+        Some(ack) => Some(Error::ExtendedAck(Box::new(ack))),
+        None => Some(io::Error::from_raw_os_error(-code).into()),
+    }
 }
 
 /// Netlink data message
@@ -225,6 +342,7 @@ impl ErrorMessage {
 ///
 /// Header is the message header, See [Header](struct.Header.html).
 /// The data is 4 byte aligned.
+#[derive(Clone)]
 pub struct Message {
     /// Message header
     pub header: Header,
@@ -260,6 +378,105 @@ impl Message {
 
 pub type Messages = Vec<Message>;
 
+/// Netlink message borrowed from a receive buffer
+///
+/// Same as [Message](struct.Message.html), but the data refers to the
+/// buffer it was received in instead of being copied.
+#[derive(Clone, Copy)]
+pub struct MessageRef<'a> {
+    header: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Get the message header
+    pub fn header(&self) -> Header {
+        Header::unpack_unchecked(self.header)
+    }
+
+    /// Get the message type
+    pub fn identifier(&self) -> u16 {
+        u16::unpack_unchecked(&self.header[4..])
+    }
+
+    /// Get the message flags
+    pub fn flags(&self) -> MessageFlags {
+        MessageFlags::from_bits_truncate(u16::unpack_unchecked(&self.header[6..]))
+    }
+
+    /// Get the message sequence number
+    pub fn sequence(&self) -> u32 {
+        u32::unpack_unchecked(&self.header[8..])
+    }
+
+    /// Get the message data, without padding
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Iterate over the attributes following a family specific header of
+    /// `offset` bytes
+    pub fn attributes(&self, offset: usize) -> AttributeIter<'a> {
+        AttributeIter::new(&self.data[offset.min(self.data.len())..])
+    }
+
+    /// Copy the message
+    pub fn to_message(&self) -> Message {
+        Message {
+            header: self.header(),
+            data: self.data.to_vec(),
+        }
+    }
+}
+
+/// Iterator over the messages of a datagram, borrowing the datagram
+///
+/// Messages are returned as received, including `NLMSG_ERROR` and
+/// `NLMSG_DONE` messages. Iteration ends after the first malformed message.
+pub struct MessageIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> MessageIter<'a> {
+    /// Create an iterator over the messages in the datagram
+    pub fn new(data: &'a [u8]) -> MessageIter<'a> {
+        MessageIter { data }
+    }
+}
+
+impl<'a> Iterator for MessageIter<'a> {
+    type Item = Result<MessageRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = self.data;
+        let length = match u32::unpack(data) {
+            Ok(length) => length as usize,
+            Err(err) => {
+                self.data = &[];
+                return Some(Err(err));
+            }
+        };
+        if length < Header::HEADER_SIZE || length > data.len() {
+            self.data = &[];
+            let kind = if length < Header::HEADER_SIZE {
+                NetlinkErrorKind::InvalidLength
+            } else {
+                NetlinkErrorKind::NotEnoughData
+            };
+            return Some(Err(NetlinkError::new(kind).into()));
+        }
+        // The padding of the last message may be missing
+        self.data = &data[netlink_align(length).min(data.len())..];
+        Some(Ok(MessageRef {
+            header: &data[..Header::HEADER_SIZE],
+            data: &data[Header::HEADER_SIZE..length],
+        }))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -404,5 +621,115 @@ mod tests {
         assert_eq!(msg.original_header.flags, 0x0011u16);
         assert_eq!(msg.original_header.sequence, u32::max_value());
         assert_eq!(msg.original_header.pid, 5u32);
+        assert!(msg.ack.is_none());
+    }
+
+    #[test]
+    fn unpack_extended_error_message() {
+        let data = [
+            0x4c, 0x00, 0x00, 0x00, // size
+            0x02, 0x00, // identifier
+            0x00, 0x02, // flags, acknowledge attributes
+            0x01, 0x00, 0x00, 0x00, // sequence
+            0x04, 0x00, 0x00, 0x00, // pid
+            0xea, 0xff, 0xff, 0xff, // error code
+            0x1c, 0x00, 0x00, 0x00, // size
+            0x10, 0x00, // identifier
+            0x05, 0x00, // flags
+            0x01, 0x00, 0x00, 0x00, // sequence
+            0x04, 0x00, 0x00, 0x00, // pid
+            0x03, 0x01, 0x00, 0x00, // generic header
+            0x08, 0x00, 0x02, 0x00, // attribute size and identifier
+            0x61, 0x62, 0x63, 0x00, // attribute data
+            0x08, 0x00, 0x01, 0x00, // message size and identifier
+            0x42, 0x61, 0x64, 0x00, // message
+            0x08, 0x00, 0x02, 0x00, // offset size and identifier
+            0x14, 0x00, 0x00, 0x00, // offset
+            0x0c, 0x00, 0x04, 0x80, // policy size and identifier
+            0x08, 0x00, 0x07, 0x00, // maximum length size and identifier
+            0x0f, 0x00, 0x00, 0x00, // maximum length
+        ];
+        let (used, header) = Header::unpack_with_size(&data).unwrap();
+        let (used, msg) = ErrorMessage::unpack(&data[used..], header).unwrap();
+        assert_eq!(used, 60usize);
+        assert_eq!(msg.code, -22);
+        let ack = msg.ack.unwrap();
+        assert_eq!(ack.message, Some(String::from("Bad")));
+        assert_eq!(ack.offset, Some(20));
+        assert_eq!(ack.attribute, Some(2));
+        assert_eq!(ack.policy.unwrap().maximum_length, Some(15));
+    }
+
+    #[test]
+    fn message_flags() {
+        assert_eq!(MessageFlags::ECHO.bits(), libc::NLM_F_ECHO as u16);
+        assert_eq!(
+            MessageFlags::DUMP_INTERRUPTED.bits(),
+            libc::NLM_F_DUMP_INTR as u16
+        );
+        assert_eq!(
+            MessageFlags::DUMP_FILTERED.bits(),
+            libc::NLM_F_DUMP_FILTERED as u16
+        );
+        assert_eq!(MessageFlags::ROOT.bits(), libc::NLM_F_ROOT as u16);
+        assert_eq!(MessageFlags::MATCH.bits(), libc::NLM_F_MATCH as u16);
+        assert_eq!(MessageFlags::ATOMIC.bits(), libc::NLM_F_ATOMIC as u16);
+        assert_eq!(MessageFlags::DUMP.bits(), libc::NLM_F_DUMP as u16);
+        assert_eq!(MessageFlags::REPLACE.bits(), libc::NLM_F_REPLACE as u16);
+        assert_eq!(MessageFlags::EXCL.bits(), libc::NLM_F_EXCL as u16);
+        assert_eq!(MessageFlags::CREATE.bits(), libc::NLM_F_CREATE as u16);
+        assert_eq!(MessageFlags::APPEND.bits(), libc::NLM_F_APPEND as u16);
+        assert_eq!(MessageFlags::CAPPED.bits(), libc::NLM_F_CAPPED as u16);
+        assert_eq!(MessageFlags::ACK_TLVS.bits(), libc::NLM_F_ACK_TLVS as u16);
+    }
+
+    #[test]
+    fn message_modes() {
+        let modes = [
+            (MessageMode::None, MessageMode::None),
+            (MessageMode::Acknowledge, MessageMode::Acknowledge),
+            (MessageMode::Dump, MessageMode::Dump),
+            (MessageMode::Create, MessageMode::Dump),
+            (MessageMode::Replace, MessageMode::Dump),
+            (MessageMode::Append, MessageMode::Acknowledge),
+        ];
+        for (mode, expected) in modes {
+            assert!(MessageMode::from(MessageFlags::from(mode)) == expected);
+        }
+        let flags = MessageFlags::from(MessageMode::Create) | MessageFlags::ECHO;
+        assert!(flags.contains(MessageFlags::CREATE | MessageFlags::EXCL | MessageFlags::ECHO));
+        let atomic = MessageFlags::REQUEST | MessageFlags::DUMP | MessageFlags::ATOMIC;
+        assert!(MessageMode::from(atomic) == MessageMode::Dump);
+
+        // ROOT or MATCH alone is a dump
+        for flags in [MessageFlags::ROOT, MessageFlags::MATCH] {
+            assert!(MessageMode::from(MessageFlags::REQUEST | flags) == MessageMode::Dump);
+        }
+        let request = MessageFlags::REQUEST | MessageFlags::ACKNOWLEDGE;
+        assert!(MessageMode::from(request | MessageFlags::ECHO) == MessageMode::Acknowledge);
+        assert!(MessageMode::from(MessageFlags::REQUEST | MessageFlags::ECHO) == MessageMode::None);
+    }
+
+    #[test]
+    fn iterate_messages() {
+        let data = [
+            0x14, 0x00, 0x00, 0x00, // size
+            0x10, 0x00, // identifier
+            0x02, 0x00, // flags
+            0x01, 0x00, 0x00, 0x00, // sequence
+            0x04, 0x00, 0x00, 0x00, // pid
+            0x11, 0x22, 0x33, 0x44, // data
+            0x08, 0x00, 0x00, 0x00, // size, too short
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut messages = MessageIter::new(&data);
+        let message = messages.next().unwrap().unwrap();
+        assert_eq!(message.identifier(), 0x10);
+        assert!(message.flags() == MessageFlags::MULTIPART);
+        assert_eq!(message.sequence(), 1);
+        assert_eq!(message.data(), &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(message.to_message().header.pid, 4);
+        assert!(messages.next().unwrap().is_err());
+        assert!(messages.next().is_none());
     }
 }
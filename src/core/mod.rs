@@ -2,12 +2,23 @@
 
 #[macro_use]
 mod helpers;
+mod acknowledge;
+mod ancillary;
+#[cfg(feature = "tokio")]
+mod async_socket;
 mod attribute;
+mod attribute_set;
+mod capture;
+mod client;
+mod dump;
+mod filter;
 mod hardware_address;
+mod ip_prefix;
 mod message;
 mod pack;
 mod socket;
 mod system;
+mod transport;
 
 extended_enum!(Protocol, i32,
     Route => 0,
@@ -34,11 +45,32 @@ extended_enum!(Protocol, i32,
     SMC => 21
 );
 
-pub use self::attribute::{nested_attribute_array, Attribute};
+pub use self::acknowledge::{AttributePolicy, ExtendedAck};
+pub use self::ancillary::{Credentials, ReceiveInfo};
+#[cfg(feature = "tokio")]
+pub use self::async_socket::{AsyncSocket, MessageStream};
+pub(crate) use self::attribute::attributes_pack_size;
+pub use self::attribute::{
+    nested_attribute_array, Attribute, AttributeIter, AttributeRef, NLA_F_NESTED,
+};
+pub use self::attribute_set::{
+    AttributeKind, AttributeRule, AttributeSet, FromAttribute, ToAttribute,
+};
+pub use self::capture::{Direction, PcapPacket, PcapReader, PcapRecorder, LINKTYPE_NETLINK};
+pub use self::client::Client;
+pub use self::dump::DumpIter;
+pub use self::filter::{Filter, FilterBuilder, FilterInstruction};
 pub use self::hardware_address::HardwareAddress;
-pub use self::message::{Header, Message, MessageFlags, MessageMode};
-pub use self::pack::{pack_vec, NativePack, NativeUnpack};
-pub use self::socket::{SendMessage, Socket};
+pub use self::ip_prefix::IpPrefix;
+pub use self::message::{Header, Message, MessageFlags, MessageIter, MessageMode, MessageRef};
+pub use self::pack::{pack_vec, BigEndian, NativePack, NativeUnpack};
+pub use self::socket::{ReceiveMode, SendMessage, Socket};
+pub use self::transport::{
+    ack_message, data_message, done_message, error_message, MemoryTransport, NetlinkTransport,
+    Transport,
+};
+#[cfg(feature = "derive")]
+pub use netlink_rust_derive::{NativePack, NativeUnpack};
 
 /// A trait for converting a value from one type to another.
 /// Any failure in converting will return None.
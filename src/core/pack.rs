@@ -1,7 +1,8 @@
 use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ptr;
 
-use byteorder::{ByteOrder, NativeEndian};
+use byteorder::{ByteOrder, NativeEndian, NetworkEndian};
 
 use crate::core::hardware_address::HardwareAddress;
 use crate::errors::Result;
@@ -117,6 +118,78 @@ impl NativeUnpack for Vec<u32> {
     }
 }
 
+impl NativeUnpack for Ipv4Addr {
+    fn unpack_unchecked(buffer: &[u8]) -> Self {
+        Ipv4Addr::new(buffer[0], buffer[1], buffer[2], buffer[3])
+    }
+}
+impl NativeUnpack for Ipv6Addr {
+    fn unpack_unchecked(buffer: &[u8]) -> Self {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&buffer[..16]);
+        Ipv6Addr::from(octets)
+    }
+}
+/// The address family is given by the length of the buffer, 4 or 16 bytes,
+/// as for attribute data
+impl NativeUnpack for IpAddr {
+    fn unpack_with_size(buffer: &[u8]) -> Result<(usize, Self)> {
+        match buffer.len() {
+            4 => Ok((4, IpAddr::V4(Ipv4Addr::unpack_unchecked(buffer)))),
+            16 => Ok((16, IpAddr::V6(Ipv6Addr::unpack_unchecked(buffer)))),
+            0..=3 => Err(NetlinkError::new(NetlinkErrorKind::NotEnoughData).into()),
+            _ => Err(NetlinkError::new(NetlinkErrorKind::InvalidLength).into()),
+        }
+    }
+    fn unpack_unchecked(buffer: &[u8]) -> Self {
+        // Like the other unchecked unpacks, too short a buffer panics
+        if buffer.len() < 16 {
+            IpAddr::V4(Ipv4Addr::unpack_unchecked(buffer))
+        } else {
+            IpAddr::V6(Ipv6Addr::unpack_unchecked(buffer))
+        }
+    }
+}
+
+/// Integer in network byte order, big endian
+///
+/// Packs and unpacks the wrapped integer in network byte order, as used for
+/// ports and other protocol fields in attributes.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BigEndian<T>(pub T);
+
+impl<T> From<T> for BigEndian<T> {
+    fn from(value: T) -> BigEndian<T> {
+        BigEndian(value)
+    }
+}
+
+macro_rules! big_endian {
+    ($ty:ty, $read:ident, $write:ident) => {
+        impl NativeUnpack for BigEndian<$ty> {
+            fn unpack_unchecked(buffer: &[u8]) -> Self {
+                BigEndian(NetworkEndian::$read(buffer))
+            }
+        }
+        impl NativePack for BigEndian<$ty> {
+            fn pack_size(&self) -> usize {
+                mem::size_of::<Self>()
+            }
+            fn pack_unchecked(&self, buffer: &mut [u8]) {
+                NetworkEndian::$write(buffer, self.0);
+            }
+        }
+    };
+}
+
+big_endian!(u16, read_u16, write_u16);
+big_endian!(i16, read_i16, write_i16);
+big_endian!(u32, read_u32, write_u32);
+big_endian!(i32, read_i32, write_i32);
+big_endian!(u64, read_u64, write_u64);
+big_endian!(i64, read_i64, write_i64);
+
 /// Pack value into byte slice, using native endian
 pub trait NativePack: Sized {
     ///
@@ -224,6 +297,36 @@ impl NativePack for HardwareAddress {
         }
     }
 }
+impl NativePack for Ipv4Addr {
+    fn pack_size(&self) -> usize {
+        4
+    }
+    fn pack_unchecked(&self, buffer: &mut [u8]) {
+        buffer[..4].copy_from_slice(&self.octets());
+    }
+}
+impl NativePack for Ipv6Addr {
+    fn pack_size(&self) -> usize {
+        16
+    }
+    fn pack_unchecked(&self, buffer: &mut [u8]) {
+        buffer[..16].copy_from_slice(&self.octets());
+    }
+}
+impl NativePack for IpAddr {
+    fn pack_size(&self) -> usize {
+        match *self {
+            IpAddr::V4(ref address) => address.pack_size(),
+            IpAddr::V6(ref address) => address.pack_size(),
+        }
+    }
+    fn pack_unchecked(&self, buffer: &mut [u8]) {
+        match *self {
+            IpAddr::V4(ref address) => address.pack_unchecked(buffer),
+            IpAddr::V6(ref address) => address.pack_unchecked(buffer),
+        }
+    }
+}
 impl NativePack for Vec<u8> {
     fn pack_size(&self) -> usize {
         self.len()
@@ -243,13 +346,13 @@ impl NativePack for Vec<u8> {
 
 /// Pack a vector of values into byte slice
 pub fn pack_vec<T: NativePack>(buffer: &mut [u8], v: &Vec<T>) -> Result<usize> {
-    let mut size = 0usize;
+    let length = buffer.len();
     let mut slice = buffer;
     for i in v {
         slice = i.pack(slice)?;
-        size += i.pack_size();
     }
-    Ok(size)
+    // Include any padding between the values
+    Ok(length - slice.len())
 }
 
 #[cfg(test)]
@@ -340,6 +443,35 @@ mod tests {
         pack_unpack_test(bytes.as_slice(), hwa);
     }
 
+    #[test]
+    fn pack_unpack_big_endian() {
+        pack_unpack_test(&[0x1f, 0x90], BigEndian(8080u16));
+        pack_unpack_test(&[0xff, 0xff, 0xff, 0xfe], BigEndian(-2i32));
+        pack_unpack_test(
+            &[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88],
+            BigEndian(0x1122334455667788u64),
+        );
+    }
+
+    #[test]
+    fn pack_unpack_ip_address() {
+        pack_unpack_test(&[192, 168, 1, 10], Ipv4Addr::new(192, 168, 1, 10));
+        let bytes = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let address = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        pack_unpack_test(&bytes, address);
+
+        assert_eq!(IpAddr::unpack(&bytes).unwrap(), IpAddr::V6(address));
+        assert_eq!(
+            IpAddr::unpack(&bytes[..4]).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(0x20, 0x01, 0x0d, 0xb8))
+        );
+        assert!(IpAddr::unpack(&bytes[..8]).is_err());
+        let mut buffer = [0u8; 16];
+        let left = IpAddr::V6(address).pack(&mut buffer).unwrap();
+        assert!(left.is_empty());
+        assert_eq!(buffer, bytes);
+    }
+
     #[test]
     fn pack_unpack_any_vec() {
         let v = vec![1u16, 2u16];
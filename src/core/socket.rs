@@ -1,17 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::File;
 use std::io;
-use std::mem::size_of;
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::mem::{self, size_of};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::time::Duration;
 
 use libc;
 
-use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
+use crate::errors::{Error, NetlinkError, NetlinkErrorKind, Result};
 
+use crate::core::ancillary::ReceiveInfo;
+use crate::core::capture::{Direction, PcapRecorder};
+use crate::core::dump::DumpIter;
+use crate::core::filter::Filter;
 use crate::core::message::{
-    netlink_align, ErrorMessage, Header, Message, MessageFlags, MessageMode, Messages,
+    netlink_align, unpack_done, ErrorMessage, Header, Message, MessageFlags, MessageIter,
+    MessageMode, Messages,
 };
 use crate::core::pack::{NativePack, NativeUnpack};
 use crate::core::system;
+use crate::core::transport::{NetlinkTransport, Transport};
 use crate::core::Protocol;
 
 /// Trait for message to be sent by the socket
@@ -22,158 +31,754 @@ pub trait SendMessage {
     fn message_type(&self) -> u16;
     /// Get the query flags
     fn query_flags(&self) -> MessageFlags;
+    /// Get the size of the packed message, excluding the message header
+    ///
+    /// Used to size the send buffer. The default implementation returns 0,
+    /// the send buffer then grows until the message fits.
+    fn pack_size(&self) -> usize {
+        0
+    }
 }
 
+/// Largest send buffer used when the message size is not known in advance
+const MAXIMUM_SEND_BUFFER_SIZE: usize = 1 << 24;
+
+/// Distance between the sequence numbers of a socket and its clones
+const CLONE_SEQUENCE_GAP: u32 = 1 << 20;
+
 const NLMSG_NOOP: u16 = 1;
 const NLMSG_ERROR: u16 = 2;
 const NLMSG_DONE: u16 = 3;
-// const NLMSG_OVERRUN: u16 = 4;
+const NLMSG_OVERRUN: u16 = 4;
 
 const NETLINK_ADD_MEMBERSHIP: i32 = 1;
-// const NETLINK_DROP_MEMBERSHIP: i32 = 2;
-// const NETLINK_PKTINFO: i32 = 3;
+const NETLINK_DROP_MEMBERSHIP: i32 = 2;
+pub(crate) const NETLINK_PKTINFO: i32 = 3;
 // const NETLINK_BROADCAST_ERROR: i32 = 4;
-// const NETLINK_NO_ENOBUFS: i32 = 5;
+const NETLINK_NO_ENOBUFS: i32 = 5;
 // const NETLINK_RX_RING: i32 = 6;
 // const NETLINK_TX_RING: i32 = 7;
-// const NETLINK_LISTEN_ALL_NSID: i32 = 8;
-// const NETLINK_LIST_MEMBERSHIPS: i32 = 9;
-// const NETLINK_CAP_ACK: i32 = 10;
-// const NETLINK_EXT_ACK: i32 = 11;
+pub(crate) const NETLINK_LISTEN_ALL_NSID: i32 = 8;
+const NETLINK_LIST_MEMBERSHIPS: i32 = 9;
+const NETLINK_CAP_ACK: i32 = 10;
+const NETLINK_EXT_ACK: i32 = 11;
+
+/// Default maximum size of a received datagram
+const DEFAULT_MAXIMUM_MESSAGE_SIZE: usize = 1 << 20;
+
+/// Receive mode of the socket
+///
+/// Decides how the receive functions behave when no data is pending on the
+/// socket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReceiveMode {
+    /// Receiving blocks until data is available
+    Blocking,
+    /// Receiving returns immediately when no data is available
+    NonBlocking,
+    /// Receiving blocks until data is available or the timeout expires, an
+    /// expired timeout is reported as an `io::ErrorKind::TimedOut` error
+    Timeout(Duration),
+}
+
+/// Result of taking a queued reply for a request
+pub(crate) enum Reply {
+    /// A reply message
+    Message(Message),
+    /// No reply is queued, but the request has not been completed
+    Pending,
+    /// The request has been completed
+    Done,
+}
+
+/// A sent request and the replies received for it
+struct Request {
+    messages: VecDeque<Message>,
+    /// Set when the request has been completed
+    result: Option<Result<()>>,
+    /// Replies are discarded, the request is removed once completed
+    cancelled: bool,
+    /// A reply of the dump was flagged as interrupted
+    interrupted: bool,
+}
+
+impl Request {
+    fn new() -> Request {
+        Request {
+            messages: VecDeque::new(),
+            result: None,
+            cancelled: false,
+            interrupted: false,
+        }
+    }
+}
 
 /// Netlink Socket can be used to communicate with the Linux kernel using the
 /// netlink protocol.
-pub struct Socket {
+///
+/// The transport defaults to a netlink socket, see
+/// [Transport](trait.Transport.html) and `with_transport`.
+pub struct Socket<T: Transport = NetlinkTransport> {
     local: system::Address,
-    peer: system::Address,
-    socket: RawFd,
+    transport: T,
+    mode: ReceiveMode,
+    kernel_only: bool,
+    peer: u32,
     sequence_next: u32,
-    page_size: usize,
+    maximum_message_size: usize,
     receive_buffer: Vec<u8>,
+    receive_info: ReceiveInfo,
     send_buffer: Vec<u8>,
-    sent: HashMap<u32, MessageMode>,
+    sent: HashMap<u32, Request>,
+    notifications: VecDeque<(Message, ReceiveInfo)>,
+    groups: BTreeSet<u32>,
+    recorder: Option<PcapRecorder>,
 }
 
 impl Socket {
     /// Create a new Socket
     pub fn new(protocol: Protocol) -> Result<Socket> {
-        Socket::new_multicast(protocol, 0)
+        Socket::new_multicast(protocol, &[])
     }
 
     /// Create a new Socket which subscribes to the provided multi-cast groups
-    pub fn new_multicast(protocol: Protocol, groups: u32) -> Result<Socket> {
-        let socket = system::netlink_socket(protocol as i32)?;
-        system::set_socket_option(socket, libc::SOL_SOCKET, libc::SO_SNDBUF, 32768)?;
-        system::set_socket_option(socket, libc::SOL_SOCKET, libc::SO_RCVBUF, 32768)?;
-        let mut local_addr = system::Address {
-            family: libc::AF_NETLINK as u16,
-            _pad: 0,
-            pid: 0,
-            groups: groups,
+    ///
+    /// Groups are identified by number, starting at 1, the same as for
+    /// `multicast_group_subscribe`.
+    pub fn new_multicast(protocol: Protocol, groups: &[u32]) -> Result<Socket> {
+        Socket::new_with_mode(protocol, groups, ReceiveMode::NonBlocking)
+    }
+
+    /// Create a new Socket in the network namespace referred to by the
+    /// provided file descriptor
+    ///
+    /// The calling thread temporarily enters the namespace while the socket
+    /// is created, which requires the `CAP_SYS_ADMIN` capability. The socket
+    /// stays in the namespace for its lifetime.
+    pub fn new_in_netns(protocol: Protocol, netns: RawFd) -> Result<Socket> {
+        let current = File::open("/proc/thread-self/ns/net")?;
+        system::set_network_namespace(netns)?;
+        let socket = Socket::new(protocol);
+        // Always return to the original namespace, even if creation failed
+        system::set_network_namespace(current.as_raw_fd())?;
+        socket
+    }
+
+    /// Create a new Socket in the network namespace at the provided path,
+    /// such as `/var/run/netns/<name>` or `/proc/<pid>/ns/net`
+    ///
+    /// See `new_in_netns`.
+    pub fn new_in_netns_path<P: AsRef<Path>>(protocol: Protocol, path: P) -> Result<Socket> {
+        let netns = File::open(path)?;
+        Socket::new_in_netns(protocol, netns.as_raw_fd())
+    }
+
+    /// Create a new Socket which subscribes to the provided multi-cast groups
+    /// and receives using the provided mode
+    pub fn new_with_mode(protocol: Protocol, groups: &[u32], mode: ReceiveMode) -> Result<Socket> {
+        Socket::open(protocol, 0, groups, mode)
+    }
+
+    /// Create a new Socket bound to the provided port id
+    ///
+    /// The port id has to be unique for the protocol, binding fails with
+    /// `EADDRINUSE` otherwise. Port id 0 lets the kernel assign one.
+    pub fn new_with_port(protocol: Protocol, port: u32) -> Result<Socket> {
+        Socket::open(protocol, port, &[], ReceiveMode::NonBlocking)
+    }
+
+    fn open(protocol: Protocol, port: u32, groups: &[u32], mode: ReceiveMode) -> Result<Socket> {
+        let socket = system::netlink_socket(protocol as i32, mode == ReceiveMode::NonBlocking)?;
+        let fd = socket.as_raw_fd();
+        if let ReceiveMode::Timeout(timeout) = mode {
+            system::set_receive_timeout(fd, Some(timeout))?;
+        }
+        system::set_socket_option(fd, libc::SOL_SOCKET, libc::SO_SNDBUF, 32768)?;
+        system::set_socket_option(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, 32768)?;
+        // Extended acknowledge is not supported before Linux 4.12
+        match system::set_socket_option(fd, libc::SOL_NETLINK, NETLINK_EXT_ACK, 1i32) {
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOPROTOOPT) => {}
+            result => result?,
+        }
+        let mut local_addr = address(port);
+        system::bind(fd, &local_addr)?;
+        system::get_socket_address(fd, &mut local_addr)?;
+        let mut socket = Socket::from_parts(NetlinkTransport::new(socket), local_addr, mode);
+        for &group in groups {
+            socket.multicast_group_subscribe(group)?;
+        }
+        Ok(socket)
+    }
+
+    /// Create a Socket from an open netlink socket descriptor
+    ///
+    /// An unbound socket is bound to a kernel assigned port id, the receive
+    /// mode is taken from the descriptor.
+    fn from_owned_fd(socket: OwnedFd) -> Socket {
+        let fd = socket.as_raw_fd();
+        let mut local_addr = address(0);
+        // Best effort, the conversion traits can not fail
+        if system::get_socket_address(fd, &mut local_addr).is_ok() && local_addr.pid == 0 {
+            let _ = system::bind(fd, &address(0));
+            let _ = system::get_socket_address(fd, &mut local_addr);
+        }
+        let mode = if system::is_nonblocking(fd).unwrap_or(false) {
+            ReceiveMode::NonBlocking
+        } else {
+            match system::get_receive_timeout(fd) {
+                Ok(Some(timeout)) => ReceiveMode::Timeout(timeout),
+                _ => ReceiveMode::Blocking,
+            }
         };
-        system::bind(socket, &mut local_addr)?;
-        system::get_socket_address(socket, &mut local_addr)?;
-        let page_size = netlink_align(system::get_page_size());
-        let peer_addr = system::Address {
-            family: libc::AF_NETLINK as u16,
-            _pad: 0,
-            pid: 0,
-            groups: groups,
+        let mut socket = Socket::from_parts(NetlinkTransport::new(socket), local_addr, mode);
+        socket.groups = socket
+            .multicast_memberships()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        socket
+    }
+
+    /// Create a new Socket sharing the underlying socket
+    ///
+    /// The clone has the same port id and receive mode, but tracks requests
+    /// separately. Its sequence numbers continue from this socket's, after a
+    /// gap, so the requests of the two sockets do not share sequence numbers.
+    ///
+    /// Replies are delivered to whichever clone receives first. A clone
+    /// receiving concurrently steals the replies to the requests of the
+    /// other clones, which are then never completed, so only one clone at a
+    /// time should receive.
+    pub fn try_clone(&self) -> Result<Socket> {
+        let transport = NetlinkTransport::new(self.transport.as_fd().try_clone_to_owned()?);
+        let mut socket = Socket::from_parts(transport, self.local, self.mode);
+        socket.sequence_next = self.sequence_next.wrapping_add(CLONE_SEQUENCE_GAP).max(1);
+        socket.kernel_only = self.kernel_only;
+        socket.peer = self.peer;
+        socket.maximum_message_size = self.maximum_message_size;
+        socket.groups = self.groups.clone();
+        Ok(socket)
+    }
+
+    /// Connect the socket to the peer with the provided port id
+    ///
+    /// Messages are then sent to the peer instead of the kernel, and only
+    /// messages from the peer are received. Port id 0 connects to the kernel.
+    pub fn connect(&mut self, port: u32) -> Result<()> {
+        system::connect(self.transport.as_raw_fd(), &address(port))?;
+        self.peer = port;
+        Ok(())
+    }
+
+    /// Change the receive mode
+    pub fn set_receive_mode(&mut self, mode: ReceiveMode) -> Result<()> {
+        system::set_nonblocking(self.transport.as_raw_fd(), mode == ReceiveMode::NonBlocking)?;
+        let timeout = match mode {
+            ReceiveMode::Timeout(timeout) => Some(timeout),
+            _ => None,
         };
-        Ok(Socket {
-            local: local_addr,
-            peer: peer_addr,
-            socket: socket,
-            sequence_next: 1,
-            page_size: page_size,
-            receive_buffer: vec![0u8; page_size],
-            send_buffer: vec![0u8; page_size],
-            sent: HashMap::new(),
-        })
+        system::set_receive_timeout(self.transport.as_raw_fd(), timeout)?;
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Enable or disable extended acknowledge, enabled by default
+    ///
+    /// With extended acknowledge the kernel reports errors with a message and
+    /// the offending attribute, see [ExtendedAck](struct.ExtendedAck.html).
+    pub fn set_extended_acknowledge(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_EXT_ACK,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Enable or disable capped acknowledge, disabled by default
+    ///
+    /// With capped acknowledge the kernel does not include the original
+    /// message in error messages, which saves receive buffer space but means
+    /// that the rejected attribute can not be identified.
+    pub fn set_capped_acknowledge(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_CAP_ACK,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Enable packet information, `NETLINK_PKTINFO`
+    ///
+    /// Provides the destination multi-cast group of received datagrams, see
+    /// `receive_notifications_with_info`.
+    pub fn set_packet_info(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_PKTINFO,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Receive multi-cast notifications from all network namespaces,
+    /// `NETLINK_LISTEN_ALL_NSID`
+    ///
+    /// Notifications are received from every namespace which has an
+    /// identifier assigned in the namespace of the socket. The identifier is
+    /// provided by `receive_notifications_with_info`. Requires the
+    /// `CAP_NET_BROADCAST` capability.
+    pub fn set_listen_all_nsid(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_LISTEN_ALL_NSID,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Enable sender credentials, `SO_PASSCRED`
+    ///
+    /// Provides the credentials of the sending process with received
+    /// datagrams, see `receive_with_info`.
+    pub fn set_pass_credentials(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Disable overrun reporting, `NETLINK_NO_ENOBUFS`
+    ///
+    /// By default a receive reports [Error::Overrun](enum.Error.html) when
+    /// the kernel had to drop messages because the receive buffer was full.
+    /// With this option set messages are still dropped, but silently.
+    pub fn set_no_enobufs(&mut self, enable: bool) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_NO_ENOBUFS,
+            i32::from(enable),
+        )?;
+        Ok(())
+    }
+
+    /// Attach a classic BPF filter to the socket, `SO_ATTACH_FILTER`
+    ///
+    /// Datagrams dropped by the filter are dropped in the kernel, before
+    /// they are queued on the socket. Replaces any attached filter. A filter
+    /// longer than `BPF_MAXINSNS` instructions fails with `EINVAL`. See
+    /// [FilterBuilder](struct.FilterBuilder.html).
+    pub fn attach_filter(&mut self, filter: &Filter) -> Result<()> {
+        let instructions = filter.instructions();
+        // The length is a u16, a longer program would be cut short
+        if instructions.len() > libc::BPF_MAXINSNS as usize {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL).into());
+        }
+        let program = libc::sock_fprog {
+            len: instructions.len() as u16,
+            filter: instructions.as_ptr() as *mut libc::sock_filter,
+        };
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_FILTER,
+            program,
+        )?;
+        Ok(())
+    }
+
+    /// Detach the filter from the socket, `SO_DETACH_FILTER`
+    pub fn detach_filter(&mut self) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_DETACH_FILTER,
+            0i32,
+        )?;
+        Ok(())
+    }
+
+    /// Lock the attached filter, `SO_LOCK_FILTER`
+    ///
+    /// The filter can no longer be replaced or detached, not even by a
+    /// process the socket is passed to.
+    pub fn lock_filter(&mut self) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_LOCK_FILTER,
+            1i32,
+        )?;
+        Ok(())
+    }
+
+    /// Get the size of the kernel receive buffer, `SO_RCVBUF`
+    ///
+    /// Note that the kernel reports double the size that was set, to account
+    /// for bookkeeping overhead.
+    pub fn socket_receive_buffer(&self) -> Result<usize> {
+        let size: i32 = system::get_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+        )?;
+        Ok(size as usize)
+    }
+
+    /// Set the size of the kernel receive buffer
+    ///
+    /// Uses `SO_RCVBUFFORCE` to go beyond the `rmem_max` limit when the
+    /// process has the `CAP_NET_ADMIN` capability, otherwise `SO_RCVBUF`.
+    pub fn set_socket_receive_buffer(&mut self, size: usize) -> Result<()> {
+        system::set_buffer_size(
+            self.transport.as_raw_fd(),
+            libc::SO_RCVBUF,
+            libc::SO_RCVBUFFORCE,
+            size,
+        )?;
+        Ok(())
+    }
+
+    /// Get the size of the kernel send buffer, `SO_SNDBUF`
+    pub fn socket_send_buffer(&self) -> Result<usize> {
+        let size: i32 = system::get_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_SNDBUF,
+        )?;
+        Ok(size as usize)
+    }
+
+    /// Set the size of the kernel send buffer
+    ///
+    /// Uses `SO_SNDBUFFORCE` when permitted, otherwise `SO_SNDBUF`.
+    pub fn set_socket_send_buffer(&mut self, size: usize) -> Result<()> {
+        system::set_buffer_size(
+            self.transport.as_raw_fd(),
+            libc::SO_SNDBUF,
+            libc::SO_SNDBUFFORCE,
+            size,
+        )?;
+        Ok(())
     }
 
     /// Subscribe to the multi-cast group provided
+    ///
+    /// Groups are numbered from 1, group numbers above 32 are supported, as
+    /// used by some generic netlink families.
     pub fn multicast_group_subscribe(&mut self, group: u32) -> Result<()> {
         system::set_socket_option(
-            self.socket,
+            self.transport.as_raw_fd(),
             libc::SOL_NETLINK,
             NETLINK_ADD_MEMBERSHIP,
             group,
         )?;
+        self.groups.insert(group);
         Ok(())
     }
 
-    fn message_header(&mut self, iov: &mut [libc::iovec]) -> libc::msghdr {
-        let addr_ptr = &mut self.peer as *mut system::Address;
-        #[cfg(not(target_env = "musl"))]
-        let hdr = {
-            let iov_len = iov.len();
-            let hdr = libc::msghdr {
-                msg_iovlen: iov_len,
-                msg_iov: iov.as_mut_ptr(),
-                msg_namelen: size_of::<system::Address>() as u32,
-                msg_name: addr_ptr as *mut libc::c_void,
-                msg_flags: 0,
-                msg_controllen: 0,
-                msg_control: 0 as *mut libc::c_void,
-            };
-            hdr
-        };
-        #[cfg(target_env = "musl")]
-        let hdr = {
-            let iov_len = iov.len() as libc::c_int;
-            let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
-            hdr.msg_iovlen = iov_len;
-            hdr.msg_iov = iov.as_mut_ptr();
-            hdr.msg_namelen = size_of::<system::Address>() as u32;
-            hdr.msg_name = addr_ptr as *mut libc::c_void;
-            hdr.msg_flags = 0;
-            hdr.msg_controllen = 0;
-            hdr.msg_control = 0 as *mut libc::c_void;
-            hdr
-        };
-        hdr
+    /// Unsubscribe from the multi-cast group provided
+    pub fn multicast_group_unsubscribe(&mut self, group: u32) -> Result<()> {
+        system::set_socket_option(
+            self.transport.as_raw_fd(),
+            libc::SOL_NETLINK,
+            NETLINK_DROP_MEMBERSHIP,
+            group,
+        )?;
+        self.groups.remove(&group);
+        Ok(())
+    }
+
+    /// Query the kernel for the multi-cast group memberships of the socket
+    ///
+    /// Unlike `multicast_groups` this also includes memberships added
+    /// through other means, such as a duplicated file descriptor. Requires
+    /// Linux 4.2 or later.
+    pub fn multicast_memberships(&self) -> Result<Vec<u32>> {
+        let mut masks = vec![0u32; 1];
+        loop {
+            let size = system::get_socket_option_slice(
+                self.transport.as_raw_fd(),
+                libc::SOL_NETLINK,
+                NETLINK_LIST_MEMBERSHIPS,
+                &mut masks,
+            )?;
+            let count = size.div_ceil(size_of::<u32>());
+            if count <= masks.len() {
+                masks.truncate(count);
+                break;
+            }
+            masks.resize(count, 0);
+        }
+        let mut groups = vec![];
+        for (index, mask) in masks.iter().enumerate() {
+            for bit in 0..32 {
+                if mask & (1 << bit) != 0 {
+                    groups.push(index as u32 * 32 + bit + 1);
+                }
+            }
+        }
+        Ok(groups)
+    }
+}
+
+impl<T: Transport> Socket<T> {
+    /// Create a new Socket using the provided transport
+    ///
+    /// The port id is used to match replies to requests, use the port id the
+    /// transport is bound to. The socket does not block when receiving, as
+    /// if in non-blocking mode.
+    pub fn with_transport(transport: T, port: u32) -> Socket<T> {
+        Socket::from_parts(transport, address(port), ReceiveMode::NonBlocking)
+    }
+
+    fn from_parts(transport: T, local: system::Address, mode: ReceiveMode) -> Socket<T> {
+        let page_size = netlink_align(system::get_page_size());
+        Socket {
+            local,
+            transport,
+            mode,
+            kernel_only: false,
+            peer: 0,
+            sequence_next: 1,
+            maximum_message_size: DEFAULT_MAXIMUM_MESSAGE_SIZE,
+            receive_buffer: vec![0u8; page_size],
+            receive_info: ReceiveInfo::default(),
+            send_buffer: vec![0u8; page_size],
+            sent: HashMap::new(),
+            notifications: VecDeque::new(),
+            groups: BTreeSet::new(),
+            recorder: None,
+        }
+    }
+
+    /// Get the port id the socket is bound to
+    pub fn port_id(&self) -> u32 {
+        self.local.pid
+    }
+
+    /// Get the receive mode
+    pub fn receive_mode(&self) -> ReceiveMode {
+        self.mode
+    }
+
+    /// Only accept datagrams sent by the kernel
+    ///
+    /// When enabled, datagrams sent by other processes, that is with a
+    /// non-zero sender port id, are silently discarded. Use this for
+    /// protocols where user space can send messages that look like kernel
+    /// messages, such as uevents or `Usersock`.
+    pub fn set_kernel_only(&mut self, enable: bool) {
+        self.kernel_only = enable;
+    }
+
+    /// Get the maximum size of a received datagram
+    pub fn maximum_message_size(&self) -> usize {
+        self.maximum_message_size
+    }
+
+    /// Set the maximum size of a received datagram, defaults to 1 MiB
+    ///
+    /// The receive buffer grows as needed to fit the pending datagram, up to
+    /// this size. A larger datagram is discarded and reported as an `EMSGSIZE`
+    /// error, if it replies to a pending request the request fails with the
+    /// error instead.
+    pub fn set_maximum_message_size(&mut self, size: usize) {
+        self.maximum_message_size = size;
+    }
+
+    /// Get the multi-cast groups subscribed to using this Socket, in order
+    pub fn multicast_groups(&self) -> Vec<u32> {
+        self.groups.iter().cloned().collect()
+    }
+
+    /// Record every datagram sent and received to a pcap file
+    ///
+    /// Returns the previous recorder, if any. Pass None to stop recording.
+    /// See [PcapRecorder](struct.PcapRecorder.html).
+    pub fn set_recorder(&mut self, recorder: Option<PcapRecorder>) -> Option<PcapRecorder> {
+        mem::replace(&mut self.recorder, recorder)
     }
 
     /// Send the provided package on the socket
-    pub fn send_message<S: SendMessage>(&mut self, payload: &S) -> Result<usize> {
-        let hdr_size = size_of::<Header>();
-        let flags = payload.query_flags();
-        let payload_size = payload.pack(&mut self.send_buffer[hdr_size..])?;
-        let size = hdr_size + payload_size;
-        let hdr = Header {
-            length: size as u32,
-            identifier: payload.message_type(),
-            flags: flags.bits(),
-            sequence: self.sequence_next,
-            pid: self.local.pid,
-        };
-        let _slice = hdr.pack(&mut self.send_buffer[..hdr_size])?;
+    ///
+    /// Returns the sequence number of the message, which can be used with
+    /// `receive_for` to receive the replies for this request. A request sent
+    /// to a peer, see `connect`, is only tracked if it requests an
+    /// acknowledge or a dump, as peers need not reply. A message larger than
+    /// the socket send buffer fails with `EMSGSIZE`, see
+    /// `set_socket_send_buffer`.
+    pub fn send_message<S: SendMessage>(&mut self, payload: &S) -> Result<u32> {
+        let sequence = self.sequence_next;
+        let (flags, tracked) = self.request_flags(payload.query_flags());
+        let size = pack_message(
+            payload,
+            flags,
+            sequence,
+            self.local.pid,
+            &mut self.send_buffer,
+        )?;
+        let size = pad_message(&mut self.send_buffer, size);
 
-        self.sent
-            .insert(self.sequence_next, MessageMode::from(flags));
-        self.sequence_next += 1;
+        self.transport.send(&[&self.send_buffer[..size]])?;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record(Direction::Outgoing, &[&self.send_buffer[..size]]);
+        }
+
+        if tracked {
+            self.sent.insert(sequence, Request::new());
+        }
+        self.sequence_next = self.sequence_next.wrapping_add(1).max(1);
+        Ok(sequence)
+    }
+
+    /// Send the provided messages in a single datagram
+    ///
+    /// Each message gets a sequence number of its own, the sequence numbers
+    /// are returned in the order of the messages. The kernel processes the
+    /// messages in order and replies to each of them separately, use
+    /// `receive_for` to receive the replies of a message. An acknowledge is
+    /// requested for messages which do not otherwise cause a reply, see
+    /// `send_message` for messages sent to a peer. The total size is limited by the socket send buffer, see
+    /// `set_socket_send_buffer`.
+    pub fn send_batch(&mut self, messages: &[&dyn SendMessage]) -> Result<Vec<u32>> {
+        let mut buffers = Vec::with_capacity(messages.len());
+        let mut sequence = self.sequence_next;
+        for message in messages {
+            // Pack in the send buffer, which is at least a page, as messages
+            // need not know their size
+            let (flags, tracked) = self.request_flags(message.query_flags());
+            let size = pack_message(
+                *message,
+                flags,
+                sequence,
+                self.local.pid,
+                &mut self.send_buffer,
+            )?;
+            let size = pad_message(&mut self.send_buffer, size);
+            buffers.push((sequence, tracked, self.send_buffer[..size].to_vec()));
+            sequence = sequence.wrapping_add(1).max(1);
+        }
+        let datagram: Vec<&[u8]> = buffers.iter().map(|(_, _, buffer)| &buffer[..]).collect();
+        self.transport.send(&datagram)?;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record(Direction::Outgoing, &datagram);
+        }
+
+        let mut sequences = Vec::with_capacity(messages.len());
+        for (sequence, tracked, _) in buffers {
+            if tracked {
+                self.sent.insert(sequence, Request::new());
+            }
+            sequences.push(sequence);
+        }
+        self.sequence_next = sequence;
+        Ok(sequences)
+    }
+
+    /// Send the provided messages in a single datagram and receive the
+    /// replies
+    ///
+    /// Returns the outcome of each message, in the order of the messages.
+    /// The error reported by the kernel for a message is returned in its
+    /// place. See `send_batch`.
+    pub fn transact_batch(
+        &mut self,
+        messages: &[&dyn SendMessage],
+    ) -> Result<Vec<Result<Messages>>> {
+        let sequences = self.send_batch(messages)?;
+        Ok(sequences
+            .into_iter()
+            .map(|sequence| self.receive_for(sequence))
+            .collect())
+    }
 
-        let sent_size = system::send(self.socket, &self.send_buffer[..size], 0)?;
-        Ok(sent_size)
+    /// Send the provided message and receive the replies for it
+    ///
+    /// Same as calling `send_message` followed by `receive_for`. For an
+    /// acknowledged request with `MessageFlags::ECHO` set the replies are the
+    /// echoed entries, received until the acknowledge.
+    pub fn transact<S: SendMessage>(&mut self, payload: &S) -> Result<Messages> {
+        let sequence = self.send_message(payload)?;
+        self.receive_for(sequence)
+    }
+
+    /// Send the provided dump request and receive the replies, repeating the
+    /// dump if it was interrupted
+    ///
+    /// The kernel flags the replies of a dump with `NLM_F_DUMP_INTR` when the
+    /// dumped table changed during the dump, the replies are then not a
+    /// consistent snapshot. The dump is repeated up to `retries` times,
+    /// after which [Error::DumpInterrupted](enum.Error.html) is returned.
+    pub fn dump_consistent<S: SendMessage>(
+        &mut self,
+        payload: &S,
+        retries: usize,
+    ) -> Result<Messages> {
+        let mut attempt = 0;
+        loop {
+            match self.transact(payload) {
+                Err(Error::DumpInterrupted) if attempt < retries => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    /// Send the provided dump request and iterate over the replies
+    ///
+    /// See [DumpIter](struct.DumpIter.html).
+    pub fn dump<S: SendMessage>(&mut self, payload: &S) -> Result<DumpIter<'_, T>> {
+        let sequence = self.send_message(payload)?;
+        Ok(DumpIter::new(self, sequence))
     }
 
     fn receive_bytes(&mut self) -> Result<usize> {
-        let mut iov = [libc::iovec {
-            iov_base: self.receive_buffer.as_mut_ptr() as *mut libc::c_void,
-            iov_len: self.page_size,
-        }];
-
-        let mut msg_header = self.message_header(&mut iov);
-        let result = system::receive_message(self.socket, &mut msg_header);
-        match result {
-            Err(err) => {
-                if err.raw_os_error() == Some(libc::EAGAIN) {
-                    return Ok(0);
+        match self.receive_datagram()? {
+            (_, true) => Err(io::Error::from_raw_os_error(libc::EMSGSIZE).into()),
+            (bytes, false) => Ok(bytes),
+        }
+    }
+
+    /// Receive one datagram into the receive buffer
+    ///
+    /// Returns the received size and if the datagram exceeded the maximum
+    /// message size. An oversized datagram is truncated and not recorded.
+    fn receive_datagram(&mut self) -> Result<(usize, bool)> {
+        loop {
+            // Peek at the size of the pending datagram so that it is never
+            // truncated, growing the receive buffer if needed
+            let size = match receive_call(self.mode, || self.transport.peek_size())? {
+                Some(size) => size,
+                None => return Ok((0, false)),
+            };
+            let too_large = size > self.maximum_message_size;
+            if size > self.receive_buffer.len() && !too_large {
+                self.receive_buffer.resize(netlink_align(size), 0);
+            }
+            let transport = &mut self.transport;
+            let buffer = &mut self.receive_buffer;
+            let (bytes, info) =
+                receive_call(self.mode, || transport.receive(buffer))?.unwrap_or_default();
+            self.receive_info = info;
+            if self.kernel_only && self.receive_info.pid != 0 {
+                // Discard datagrams sent by other processes
+                continue;
+            }
+            if !too_large {
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.record(Direction::Incoming, &[&self.receive_buffer[..bytes]]);
                 }
-                Err(err.into())
             }
-            Ok(bytes) => Ok(bytes),
+            return Ok((bytes, too_large));
         }
     }
 
@@ -183,89 +788,1125 @@ impl Socket {
         Ok(self.receive_buffer[0..bytes].to_vec())
     }
 
+    /// Receive binary data on the socket along with information about the
+    /// sender
+    ///
+    /// The data is empty if no data was pending in non-blocking mode.
+    pub fn receive_with_info(&mut self) -> Result<(Vec<u8>, ReceiveInfo)> {
+        let bytes = self.receive_bytes()?;
+        Ok((self.receive_buffer[0..bytes].to_vec(), self.receive_info))
+    }
+
+    /// Receive a datagram and iterate over its messages without copying them
+    ///
+    /// The messages borrow the receive buffer of the socket. They are not
+    /// matched with requests, use this on sockets receiving multi-cast
+    /// notifications, such as conntrack events or uevents. Notifications
+    /// already queued by other receive functions are not returned. The
+    /// iterator is empty if no data was pending in non-blocking mode.
+    pub fn receive_message_refs(&mut self) -> Result<MessageIter<'_>> {
+        let bytes = self.receive_bytes()?;
+        Ok(MessageIter::new(&self.receive_buffer[..bytes]))
+    }
+
     /// Receive Messages pending on the socket
+    ///
+    /// Returns the queued notifications followed by the replies of all
+    /// requests. If any request failed the error is returned instead.
     pub fn receive_messages(&mut self) -> Result<Messages> {
-        let mut more_messages = true;
-        let mut result_messages = Vec::new();
-        while more_messages {
-            match self.receive_bytes() {
-                Err(err) => {
-                    return Err(err);
-                }
-                Ok(bytes) => {
-                    if bytes == 0 {
-                        break;
-                    }
-                    more_messages = self.unpack_data(bytes, &mut result_messages)?;
+        while self.receive_once()? {
+            if !self.is_pending() {
+                break;
+            }
+        }
+        self.take_messages()
+    }
+
+    /// Receive Messages until the pending requests have been completed
+    ///
+    /// Unlike `receive_messages` this does not return when the socket runs
+    /// out of data, instead it waits for the final `NLMSG_DONE` of a dump or
+    /// the acknowledge of the request. In non-blocking mode the socket is
+    /// polled while waiting. In timeout mode an expired timeout results in a
+    /// `io::ErrorKind::TimedOut` error.
+    pub fn receive_messages_until_done(&mut self) -> Result<Messages> {
+        while self.is_pending() {
+            self.receive_or_wait()?;
+        }
+        self.take_messages()
+    }
+
+    /// Receive the replies for the request with the provided sequence number
+    ///
+    /// Waits until the request has been completed, see
+    /// `receive_messages_until_done`. Messages for other requests and
+    /// multi-cast notifications received meanwhile are queued. Note that the
+    /// kernel only runs one dump at a time per socket, a dump requested while
+    /// another is in progress fails with `EBUSY`.
+    pub fn receive_for(&mut self, sequence: u32) -> Result<Messages> {
+        loop {
+            if let Some(result) = self.take_reply(sequence) {
+                return result;
+            }
+            self.receive_or_wait()?;
+        }
+    }
+
+    /// Receive multi-cast notifications
+    ///
+    /// Returns the queued notifications, or if there are none, receives until
+    /// at least one notification has arrived. In non-blocking mode an empty
+    /// list is returned if no data is pending.
+    ///
+    /// If the receive buffer overran [Error::Overrun](enum.Error.html) is
+    /// returned and some notifications have been lost. Receiving can
+    /// continue, but a monitor should re-synchronize its state, typically by
+    /// dumping the current state and then applying the notifications received
+    /// after the dump. Increasing the buffer size with
+    /// `set_socket_receive_buffer` makes overruns less likely.
+    pub fn receive_notifications(&mut self) -> Result<Messages> {
+        let notifications = self.receive_notifications_with_info()?;
+        Ok(notifications
+            .into_iter()
+            .map(|(message, _)| message)
+            .collect())
+    }
+
+    /// Receive multi-cast notifications along with information about the
+    /// datagram each was received in
+    ///
+    /// See `receive_notifications`, `set_packet_info` and
+    /// `set_listen_all_nsid`.
+    pub fn receive_notifications_with_info(&mut self) -> Result<Vec<(Message, ReceiveInfo)>> {
+        while self.notifications.is_empty() {
+            if !self.receive_once()? {
+                break;
+            }
+        }
+        Ok(self.notifications.drain(..).collect())
+    }
+
+    /// Receive and unpack at most one datagram
+    ///
+    /// Returns false if no data was pending.
+    pub(crate) fn receive_once(&mut self) -> Result<bool> {
+        let bytes = match self.receive_datagram()? {
+            (bytes, true) => return self.fail_oversized(bytes),
+            (0, false) => return Ok(false),
+            (bytes, false) => bytes,
+        };
+        self.unpack_data(bytes)?;
+        Ok(true)
+    }
+
+    /// Fail the request an oversized datagram replies to
+    ///
+    /// Only the start of the datagram has been received, the header of the
+    /// first message identifies the request. If the datagram is not a reply
+    /// to a pending request the `EMSGSIZE` error is returned instead.
+    fn fail_oversized(&mut self, bytes: usize) -> Result<bool> {
+        let error = || io::Error::from_raw_os_error(libc::EMSGSIZE).into();
+        let header = match Header::unpack(&self.receive_buffer[..bytes]) {
+            Ok(header) => header,
+            Err(_) => return Err(error()),
+        };
+        let ours = header.pid == self.local.pid && self.receive_info.group.unwrap_or(0) == 0;
+        match self.sent.get_mut(&header.sequence) {
+            Some(request) if ours && request.result.is_none() => {
+                request.result = Some(Err(error()));
+                if request.cancelled {
+                    self.sent.remove(&header.sequence);
                 }
+                Ok(true)
+            }
+            _ => Err(error()),
+        }
+    }
+
+    /// Receive and unpack one datagram, waiting for data in non-blocking mode
+    pub(crate) fn receive_or_wait(&mut self) -> Result<()> {
+        if !self.receive_once()? {
+            self.transport.wait(None)?;
+        }
+        Ok(())
+    }
+
+    /// Discard the queued and future replies for the request
+    pub(crate) fn cancel(&mut self, sequence: u32) {
+        if let Some(request) = self.sent.get_mut(&sequence) {
+            if request.result.is_some() {
+                self.sent.remove(&sequence);
+            } else {
+                request.messages.clear();
+                request.cancelled = true;
             }
         }
-        Ok(result_messages)
     }
 
-    fn check_sequence(&self, sequence: &u32) -> bool {
-        if *sequence == 0 {
-            return true;
+    /// Stop waiting for the replies of the request
+    ///
+    /// The request is no longer pending, replies received later are queued
+    /// as notifications. Returns false if the request is not tracked.
+    pub fn forget(&mut self, sequence: u32) -> bool {
+        self.sent.remove(&sequence).is_some()
+    }
+
+    /// Get the flags a request is sent with, and if the request is tracked
+    ///
+    /// Requests are completed by the acknowledge, the final `NLMSG_DONE` of a
+    /// dump or an error. Most requests get no reply at all without an
+    /// acknowledge, so the kernel is asked to acknowledge requests which
+    /// would otherwise never be completed. ROOT or MATCH alone is a dump for
+    /// get requests, but REPLACE or EXCL for others, so these are acknowledged
+    /// as well, the kernel does not acknowledge dumps. Peers need not
+    /// acknowledge, their requests are only tracked if a reply is requested.
+    fn request_flags(&self, flags: MessageFlags) -> (MessageFlags, bool) {
+        if self.peer == 0 && !flags.contains(MessageFlags::DUMP) {
+            (flags | MessageFlags::ACKNOWLEDGE, true)
+        } else if MessageMode::from(flags) != MessageMode::None {
+            (flags, true)
+        } else {
+            (flags, false)
+        }
+    }
+
+    /// Check if the request has been sent and is not yet taken
+    pub(crate) fn is_outstanding(&self, sequence: u32) -> bool {
+        self.sent.contains_key(&sequence)
+    }
+
+    /// Take the next queued reply for the request
+    pub(crate) fn next_reply(&mut self, sequence: u32) -> Result<Reply> {
+        let request = match self.sent.get_mut(&sequence) {
+            Some(request) => request,
+            None => return Err(NetlinkError::new(NetlinkErrorKind::NotFound).into()),
+        };
+        if let Some(message) = request.messages.pop_front() {
+            return Ok(Reply::Message(message));
+        }
+        if request.result.is_none() {
+            return Ok(Reply::Pending);
+        }
+        let request = self.sent.remove(&sequence).unwrap();
+        request.result.unwrap().map(|_| Reply::Done)
+    }
+
+    /// Take the next queued multi-cast notification
+    pub(crate) fn next_notification(&mut self) -> Option<Message> {
+        self.notifications.pop_front().map(|(message, _)| message)
+    }
+
+    /// Take all replies for the request if it has been completed
+    fn take_reply(&mut self, sequence: u32) -> Option<Result<Messages>> {
+        match self.sent.get(&sequence) {
+            None => Some(Err(NetlinkError::new(NetlinkErrorKind::NotFound).into())),
+            Some(request) if request.result.is_none() => None,
+            Some(_) => {
+                let Request {
+                    messages, result, ..
+                } = self.sent.remove(&sequence).unwrap();
+                Some(result.unwrap().map(|_| messages.into()))
+            }
         }
-        self.sent.contains_key(sequence)
     }
 
-    fn expect_more(&self, sequence: &u32) -> bool {
-        if *sequence == 0 {
-            return false;
+    /// Take all queued messages, removing the completed requests
+    fn take_messages(&mut self) -> Result<Messages> {
+        let mut messages: Messages = self
+            .notifications
+            .drain(..)
+            .map(|(message, _)| message)
+            .collect();
+        let mut sequences: Vec<u32> = self.sent.keys().cloned().collect();
+        sequences.sort_unstable();
+        let mut error = None;
+        for sequence in sequences {
+            let request = self.sent.get_mut(&sequence).unwrap();
+            if request.cancelled {
+                continue;
+            }
+            messages.extend(request.messages.drain(..));
+            if request.result.is_some() {
+                if let Some(Err(err)) = self.sent.remove(&sequence).and_then(|r| r.result) {
+                    error.get_or_insert(err);
+                }
+            }
         }
-        assert!(self.sent.contains_key(sequence));
-        if let Some(f) = self.sent.get(sequence) {
-            return *f != MessageMode::None;
+        match error {
+            Some(err) => Err(err),
+            None => Ok(messages),
         }
-        false
     }
 
-    fn unpack_data(&mut self, bytes: usize, messages: &mut Messages) -> Result<bool> {
-        let mut more_messages = false;
+    /// Check if any request is waiting for replies
+    pub(crate) fn is_pending(&self) -> bool {
+        self.sent
+            .values()
+            .any(|r| r.result.is_none() && !r.cancelled)
+    }
+
+    /// Unpack the received data, queueing each message either with the
+    /// request it replies to or as a notification
+    fn unpack_data(&mut self, bytes: usize) -> Result<()> {
         let data = &self.receive_buffer[..bytes];
         let mut pos = 0;
+        let mut overrun = false;
         while pos < bytes {
             let (used, header) = Header::unpack_with_size(&data[pos..])?;
-
-            pos = pos + used;
-            if !header.check_pid(self.local.pid) {
-                return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into());
+            pos += used;
+            let sequence = header.sequence;
+            // Replies are sent to the port of the request, notifications
+            // caused by a request carry its port id and sequence too, but
+            // are sent to a multi-cast group
+            let ours = header.pid == self.local.pid && self.receive_info.group.unwrap_or(0) == 0;
+            let mut request = if ours {
+                self.sent.get_mut(&sequence)
+            } else {
+                None
+            };
+            if header.flags().contains(MessageFlags::DUMP_INTERRUPTED) {
+                if let Some(ref mut request) = request {
+                    request.interrupted = true;
+                }
             }
-            if !self.check_sequence(&header.sequence) {
-                return Err(NetlinkError::new(NetlinkErrorKind::InvalidValue).into());
+            let data_length = header.aligned_data_length();
+            if data.len() < pos + data_length {
+                return Err(NetlinkError::new(NetlinkErrorKind::NotEnoughData).into());
             }
-            let sequence = header.sequence;
             if header.identifier == NLMSG_NOOP {
-                continue;
+                pos += data_length;
+            } else if header.identifier == NLMSG_OVERRUN {
+                pos += data_length;
+                match request {
+                    Some(request) => request.result = Some(Err(Error::Overrun)),
+                    None => overrun = true,
+                }
             } else if header.identifier == NLMSG_ERROR {
-                self.sent.remove(&sequence);
-                let (used, emsg) = ErrorMessage::unpack(&data[pos..], header)?;
-                pos = pos + used;
-                if emsg.code != 0 {
-                    return Err(io::Error::from_raw_os_error(-emsg.code).into());
-                } else {
-                    more_messages = false;
+                let (_, emsg) = ErrorMessage::unpack(&data[pos..], header)?;
+                pos += data_length;
+                if let Some(request) = request {
+                    // Keep the error of a request failed earlier
+                    request
+                        .result
+                        .get_or_insert(emsg.error().map_or(Ok(()), Err));
                 }
             } else if header.identifier == NLMSG_DONE {
-                self.sent.remove(&sequence);
-                more_messages = false;
-                pos = pos + header.aligned_data_length();
+                // A dump can be terminated by an error code in the done message
+                let error = unpack_done(&data[pos..], &header);
+                pos += data_length;
+                if let Some(request) = request {
+                    let interrupted = request.interrupted;
+                    request.result.get_or_insert(match error {
+                        Some(err) => Err(err),
+                        None if interrupted => Err(Error::DumpInterrupted),
+                        None => Ok(()),
+                    });
+                }
             } else {
-                let flags = MessageFlags::from_bits(header.flags).unwrap_or(MessageFlags::empty());
-                more_messages =
-                    flags.contains(MessageFlags::MULTIPART) || self.expect_more(&sequence);
                 let (used, msg) = Message::unpack(&data[pos..], header)?;
-                pos = pos + used;
-                messages.push(msg);
+                pos += used;
+                match request {
+                    Some(request) => {
+                        if !request.cancelled {
+                            request.messages.push_back(msg);
+                        }
+                    }
+                    None => self.notifications.push_back((msg, self.receive_info)),
+                }
             }
+            if ours {
+                if let Some(request) = self.sent.get(&sequence) {
+                    if request.cancelled && request.result.is_some() {
+                        self.sent.remove(&sequence);
+                    }
+                }
+            }
+        }
+        if overrun {
+            return Err(Error::Overrun);
+        }
+        Ok(())
+    }
+}
+
+/// Call a receive function, retrying if interrupted
+///
+/// Returns None if the call would block in non-blocking mode.
+fn receive_call<R, F>(mode: ReceiveMode, mut call: F) -> Result<Option<R>>
+where
+    F: FnMut() -> io::Result<R>,
+{
+    loop {
+        match call() {
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOBUFS) => {
+                return Err(Error::Overrun);
+            }
+            Err(err) => match err.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => {
+                    return match mode {
+                        ReceiveMode::NonBlocking => Ok(None),
+                        ReceiveMode::Timeout(_) => {
+                            Err(io::Error::from(io::ErrorKind::TimedOut).into())
+                        }
+                        ReceiveMode::Blocking => Err(err.into()),
+                    };
+                }
+                _ => return Err(err.into()),
+            },
+            Ok(value) => return Ok(Some(value)),
+        }
+    }
+}
+
+/// Pack the header and payload of a message into the buffer, returns the size
+/// of the message
+///
+/// The buffer is grown to fit the message.
+fn pack_message<S: SendMessage + ?Sized>(
+    payload: &S,
+    flags: MessageFlags,
+    sequence: u32,
+    pid: u32,
+    buffer: &mut Vec<u8>,
+) -> Result<usize> {
+    let hdr_size = size_of::<Header>();
+    let required = netlink_align(hdr_size + payload.pack_size());
+    if buffer.len() < required {
+        buffer.resize(required, 0);
+    }
+    let payload_size = loop {
+        match payload.pack(&mut buffer[hdr_size..]) {
+            Err(Error::Netlink(NetlinkError {
+                kind: NetlinkErrorKind::NotEnoughData,
+            })) if buffer.len() < MAXIMUM_SEND_BUFFER_SIZE => {
+                let size = (buffer.len() * 2).max(hdr_size * 2);
+                buffer.resize(size, 0);
+            }
+            result => break result?,
         }
-        return Ok(more_messages);
+    };
+    let size = hdr_size + payload_size;
+    let hdr = Header {
+        length: size as u32,
+        identifier: payload.message_type(),
+        flags: flags.bits(),
+        sequence,
+        pid,
+    };
+    hdr.pack(&mut buffer[..hdr_size])?;
+    Ok(size)
+}
+
+/// Zero the padding following a packed message, returns the aligned size of
+/// the message
+///
+/// Each message of a batch has to start at an aligned offset, and peers,
+/// unlike the kernel, do not accept a message shorter than its aligned
+/// length.
+fn pad_message(buffer: &mut [u8], size: usize) -> usize {
+    let aligned = netlink_align(size);
+    buffer[size..aligned].fill(0);
+    aligned
+}
+
+/// Create a netlink address for the provided port id
+fn address(port: u32) -> system::Address {
+    system::Address {
+        family: libc::AF_NETLINK as u16,
+        _pad: 0,
+        pid: port,
+        groups: 0,
     }
 }
 
 impl AsRawFd for Socket {
     fn as_raw_fd(&self) -> RawFd {
-        self.socket
+        self.transport.as_raw_fd()
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.transport.as_fd()
+    }
+}
+
+impl IntoRawFd for Socket {
+    fn into_raw_fd(self) -> RawFd {
+        self.transport.into_raw_fd()
+    }
+}
+
+impl FromRawFd for Socket {
+    /// Create a Socket from a netlink socket descriptor, for example one
+    /// passed by systemd or a parent process
+    ///
+    /// An unbound socket is bound to a kernel assigned port id, the receive
+    /// mode is taken from the descriptor.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor must be an open netlink socket, owned by the caller.
+    unsafe fn from_raw_fd(fd: RawFd) -> Socket {
+        Socket::from_owned_fd(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+impl From<OwnedFd> for Socket {
+    /// Create a Socket from a netlink socket descriptor, see `from_raw_fd`
+    fn from(fd: OwnedFd) -> Socket {
+        Socket::from_owned_fd(fd)
+    }
+}
+
+impl From<Socket> for OwnedFd {
+    fn from(socket: Socket) -> OwnedFd {
+        socket.transport.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::attribute::Attribute;
+    use crate::core::filter::{FilterBuilder, FilterInstruction};
+    use crate::route;
+
+    #[test]
+    fn grow_receive_buffer() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        let count = socket.transact(&msg).unwrap().len();
+        socket.receive_buffer = vec![0u8; 64];
+        assert_eq!(socket.transact(&msg).unwrap().len(), count);
+        assert!(socket.receive_buffer.len() > 64);
+    }
+
+    #[test]
+    fn maximum_message_size() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        socket.set_maximum_message_size(64);
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        let err = socket.transact(&msg).err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+    }
+
+    fn broadcast(count: usize) {
+        broadcast_message(1, 16, count);
+    }
+
+    fn broadcast_message(group: u32, identifier: u16, count: usize) {
+        let sender = system::netlink_socket(Protocol::Usersock as i32, false).unwrap();
+        let group = system::Address {
+            family: libc::AF_NETLINK as u16,
+            _pad: 0,
+            pid: 0,
+            groups: 1 << (group - 1),
+        };
+        let mut data = [0u8; 16];
+        Header {
+            length: 16,
+            identifier,
+            flags: 0,
+            sequence: 0,
+            pid: 0,
+        }
+        .pack(&mut data)
+        .unwrap();
+        for _ in 0..count {
+            // Broadcast to the group, the unicast to the kernel is refused
+            unsafe {
+                libc::sendto(
+                    sender.as_raw_fd(),
+                    data.as_ptr() as *const libc::c_void,
+                    data.len(),
+                    0,
+                    &group as *const system::Address as *const libc::sockaddr,
+                    size_of::<system::Address>() as libc::socklen_t,
+                );
+            }
+        }
+    }
+
+    fn receive_broadcast(receiver: &mut Socket, count: usize) -> (usize, bool) {
+        broadcast(count);
+        let (mut received, mut overrun) = (0, false);
+        loop {
+            match receiver.receive_notifications() {
+                Ok(messages) if messages.is_empty() => break,
+                Ok(messages) => received += messages.len(),
+                Err(Error::Overrun) => overrun = true,
+                Err(err) => panic!("{}", err),
+            }
+        }
+        (received, overrun)
+    }
+
+    #[test]
+    fn multicast_overrun() {
+        let mut receiver = Socket::new_multicast(Protocol::Usersock, &[1]).unwrap();
+        let (received, overrun) = receive_broadcast(&mut receiver, 1000);
+        assert!(overrun);
+        assert!(received > 0 && received < 1000);
+        // The socket is usable after the overrun
+        assert_eq!(receive_broadcast(&mut receiver, 1), (1, false));
+
+        let mut receiver = Socket::new_multicast(Protocol::Usersock, &[1]).unwrap();
+        receiver.set_no_enobufs(true).unwrap();
+        let (received, overrun) = receive_broadcast(&mut receiver, 1000);
+        assert!(!overrun);
+        assert!(received < 1000);
+    }
+
+    #[test]
+    fn multicast_memberships() {
+        // Route has more than 32 groups
+        let mut socket = Socket::new_multicast(Protocol::Route, &[1, 33]).unwrap();
+        assert_eq!(socket.multicast_groups(), vec![1, 33]);
+        assert_eq!(socket.multicast_memberships().unwrap(), vec![1, 33]);
+        socket.multicast_group_subscribe(3).unwrap();
+        socket.multicast_group_unsubscribe(33).unwrap();
+        assert_eq!(socket.multicast_groups(), vec![1, 3]);
+        assert_eq!(socket.multicast_memberships().unwrap(), vec![1, 3]);
+        assert!(socket.multicast_group_subscribe(0).is_err());
+        assert_eq!(socket.multicast_groups(), vec![1, 3]);
+    }
+
+    #[test]
+    fn receive_info() {
+        let mut receiver = Socket::new_multicast(Protocol::Usersock, &[1]).unwrap();
+        broadcast(1);
+        let notifications = receiver.receive_notifications_with_info().unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].1.group, None);
+
+        receiver.set_packet_info(true).unwrap();
+        receiver.set_listen_all_nsid(true).unwrap();
+        broadcast(1);
+        let notifications = receiver.receive_notifications_with_info().unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].1.group, Some(1));
+        assert_eq!(notifications[0].1.nsid, None);
+    }
+
+    #[test]
+    fn receive_sender_info() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        socket
+            .send_message(&route::Message::new(route::FamilyId::GetLink))
+            .unwrap();
+        let (data, info) = socket.receive_with_info().unwrap();
+        assert!(!data.is_empty());
+        assert_eq!(info.pid, 0);
+        assert_eq!(info.credentials, None);
+
+        let mut receiver = Socket::new_multicast(Protocol::Usersock, &[1]).unwrap();
+        receiver.set_pass_credentials(true).unwrap();
+        broadcast(1);
+        let (data, info) = receiver.receive_with_info().unwrap();
+        assert_eq!(data.len(), 16);
+        assert_ne!(info.pid, 0);
+        let credentials = info.credentials.unwrap();
+        assert_eq!(credentials.pid, std::process::id() as i32);
+        assert_eq!(credentials.uid, unsafe { libc::getuid() });
+
+        receiver.set_kernel_only(true);
+        broadcast(1);
+        let (data, _) = receiver.receive_with_info().unwrap();
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn wait_interrupted() {
+        extern "C" fn ignore(_: libc::c_int) {}
+
+        // Interrupt the wait with a signal, without restarting the call
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut());
+        }
+        let thread = unsafe { libc::pthread_self() };
+        let signaller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            unsafe { libc::pthread_kill(thread, libc::SIGUSR1) };
+        });
+        let socket = Socket::new(Protocol::Route).unwrap();
+        let start = std::time::Instant::now();
+        let timeout = Duration::from_millis(100);
+        assert!(!system::poll_read(socket.as_raw_fd(), Some(timeout)).unwrap());
+        assert!(start.elapsed() >= timeout);
+        signaller.join().unwrap();
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        let fd = socket.as_raw_fd();
+        // A zero timeout must not disable the timeout, the kernel rounds it
+        // up to a clock tick
+        for timeout in [Duration::ZERO, Duration::from_nanos(1)] {
+            socket
+                .set_receive_mode(ReceiveMode::Timeout(timeout))
+                .unwrap();
+            assert!(system::get_receive_timeout(fd).unwrap().is_some());
+            match socket.receive_messages() {
+                Err(Error::Io(ref err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+                _ => panic!("expected a timeout"),
+            }
+        }
+        socket.set_receive_mode(ReceiveMode::Blocking).unwrap();
+        assert_eq!(system::get_receive_timeout(fd).unwrap(), None);
+    }
+
+    #[test]
+    fn descriptor_ownership() {
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        let socket = Socket::new_with_mode(Protocol::Route, &[], ReceiveMode::Blocking).unwrap();
+        let port = socket.port_id();
+        let mut socket = unsafe { Socket::from_raw_fd(socket.into_raw_fd()) };
+        assert_eq!(socket.port_id(), port);
+        assert_eq!(socket.receive_mode(), ReceiveMode::Blocking);
+        assert!(!socket.transact(&msg).unwrap().is_empty());
+
+        let mut clone = socket.try_clone().unwrap();
+        assert_eq!(clone.port_id(), port);
+        assert_eq!(
+            clone.sequence_next,
+            socket.sequence_next + CLONE_SEQUENCE_GAP
+        );
+        assert!(!clone.transact(&msg).unwrap().is_empty());
+
+        // An unbound descriptor gets bound on conversion
+        let fd = system::netlink_socket(Protocol::Route as i32, true).unwrap();
+        let mut socket = Socket::from(fd);
+        assert_ne!(socket.port_id(), 0);
+        assert_eq!(socket.receive_mode(), ReceiveMode::NonBlocking);
+        assert!(!socket.transact(&msg).unwrap().is_empty());
+        let _fd: OwnedFd = socket.into();
+    }
+
+    #[test]
+    fn connect_to_port() {
+        let port = 0x7000_0000 + std::process::id();
+        let mut receiver = Socket::new_with_port(Protocol::Usersock, port).unwrap();
+        assert_eq!(receiver.port_id(), port);
+        assert!(Socket::new_with_port(Protocol::Usersock, port).is_err());
+
+        let mut sender = Socket::new(Protocol::Usersock).unwrap();
+        sender.connect(port).unwrap();
+        sender
+            .send_message(&route::Message::new(route::FamilyId::GetLink))
+            .unwrap();
+        let (data, info) = receiver.receive_with_info().unwrap();
+        assert!(!data.is_empty());
+        assert_eq!(info.pid, sender.port_id());
+    }
+
+    #[test]
+    fn send_buffer_limit() {
+        use crate::generic;
+
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        socket.set_socket_send_buffer(4096).unwrap();
+        let size = socket.socket_send_buffer().unwrap();
+        let mut msg = generic::Message::new(0x10u16, 3u8, MessageMode::Acknowledge);
+        msg.append_attribute(Attribute::new_bytes(1u16, &vec![0u8; 3 * size]));
+        let err = socket.send_message(&msg).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+        // The send buffer is left as configured
+        assert_eq!(socket.socket_send_buffer().unwrap(), size);
+        socket.set_socket_send_buffer(4 * size).unwrap();
+        socket.send_message(&msg).unwrap();
+    }
+
+    #[test]
+    fn peer_requests() {
+        use crate::generic;
+
+        let mut receiver = Socket::new(Protocol::Usersock).unwrap();
+        let mut sender = Socket::new(Protocol::Usersock).unwrap();
+        sender.connect(receiver.port_id()).unwrap();
+        // The peer is not asked to acknowledge, and is not waited for
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::None);
+        sender.send_message(&msg).unwrap();
+        assert!(!sender.is_pending());
+        let data = receiver.receive().unwrap();
+        let header = Header::unpack(&data).unwrap();
+        assert!(!header.flags().contains(MessageFlags::ACKNOWLEDGE));
+
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::Acknowledge);
+        let sequence = sender.send_message(&msg).unwrap();
+        assert!(sender.is_pending());
+        assert!(sender.forget(sequence));
+        assert!(!sender.is_pending());
+        assert!(!sender.forget(sequence));
+    }
+
+    #[test]
+    fn new_in_netns() {
+        let mut socket = Socket::new_in_netns_path(Protocol::Route, "/proc/self/ns/net").unwrap();
+        let msg = route::Message::new(route::FamilyId::GetLink);
+        assert!(!socket.transact(&msg).unwrap().is_empty());
+    }
+
+    #[test]
+    fn socket_buffers() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        socket.set_socket_receive_buffer(65536).unwrap();
+        assert!(socket.socket_receive_buffer().unwrap() >= 65536);
+        socket.set_socket_send_buffer(65536).unwrap();
+        assert!(socket.socket_send_buffer().unwrap() >= 65536);
+    }
+
+    #[test]
+    fn fake_kernel_acknowledge() {
+        use crate::core::transport::{ack_message, error_message, MemoryTransport};
+        use crate::generic;
+
+        let transport = MemoryTransport::new();
+        transport.set_handler(|datagram| {
+            // Acknowledge known families, fail unknown ones
+            let mut replies = vec![];
+            let mut data = datagram;
+            while !data.is_empty() {
+                let header = Header::unpack(data).unwrap();
+                replies.push(if header.identifier == 0x10 {
+                    ack_message(&header)
+                } else {
+                    error_message(&header, -libc::ENOENT)
+                });
+                data = &data[header.aligned_length()..];
+            }
+            replies
+        });
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        let known = generic::Message::new(0x10u16, 3u8, MessageMode::Acknowledge);
+        let unknown = generic::Message::new(0x7fu16, 3u8, MessageMode::Acknowledge);
+        let results = socket.transact_batch(&[&known, &unknown, &known]).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].as_ref().unwrap().is_empty());
+        match results[1] {
+            Err(ref err) => assert_eq!(err.raw_os_error(), Some(libc::ENOENT)),
+            Ok(_) => panic!("expected an error"),
+        }
+        assert!(results[2].as_ref().unwrap().is_empty());
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        let header = Header::unpack(&sent[0]).unwrap();
+        assert_eq!((header.sequence, header.pid), (1, 200));
+        assert!(header.flags().contains(MessageFlags::ACKNOWLEDGE));
+
+        // Nothing pending, the fake kernel does not block
+        assert!(socket.receive_messages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn replies_for_other_ports() {
+        use crate::core::transport::{ack_message, data_message, MemoryTransport};
+        use crate::generic;
+
+        let transport = MemoryTransport::new();
+        transport.set_handler(|datagram| {
+            // Reply as if the request came from another port
+            let mut header = Header::unpack(datagram).unwrap();
+            header.pid += 1;
+            let reply = data_message(&header, 0x10, MessageFlags::empty(), &[3, 1, 0, 0]);
+            vec![reply, ack_message(&header)]
+        });
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::Acknowledge);
+        let sequence = socket.send_message(&msg).unwrap();
+        assert!(socket.receive_once().unwrap());
+        // The reply does not complete the request of this port
+        assert!(socket.is_pending());
+        assert!(socket.take_reply(sequence).is_none());
+        assert_eq!(socket.notifications.len(), 1);
+        assert_eq!(socket.notifications[0].0.header.pid, 201);
+    }
+
+    #[test]
+    fn kernel_request_flags() {
+        use crate::core::transport::MemoryTransport;
+
+        let socket = Socket::with_transport(MemoryTransport::new(), 200);
+        let request = MessageFlags::REQUEST;
+        let dump = request | MessageFlags::DUMP;
+        assert!(socket.request_flags(dump) == (dump, true));
+        // A new request replacing an entry is not completed by a done
+        for flags in [
+            request | MessageFlags::REPLACE,
+            request | MessageFlags::ROOT,
+        ] {
+            assert!(socket.request_flags(flags) == (flags | MessageFlags::ACKNOWLEDGE, true));
+        }
+    }
+
+    #[test]
+    fn oversized_reply() {
+        use crate::core::transport::{data_message, done_message, MemoryTransport};
+        use crate::generic;
+
+        let transport = MemoryTransport::new();
+        transport.set_handler(|datagram| {
+            let header = Header::unpack(datagram).unwrap();
+            let flags = MessageFlags::MULTIPART;
+            vec![
+                data_message(&header, 0x10, flags, &[1u8; 256]),
+                data_message(&header, 0x10, flags, &[2u8; 16]),
+                done_message(&header),
+            ]
+        });
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        socket.set_maximum_message_size(128);
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::Dump);
+        let sequence = socket.send_message(&msg).unwrap();
+        // The oversized reply fails the dump, the done does not complete it
+        assert!(socket.receive_once().unwrap());
+        assert!(!socket.is_pending());
+        while socket.receive_once().unwrap() {}
+        assert!(socket.notifications.is_empty());
+        let err = socket.take_reply(sequence).unwrap().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+
+        // Oversized datagrams for no request are reported by the receive call
+        transport.push(data_message(
+            &Header {
+                length: 0,
+                identifier: 0x10,
+                flags: 0,
+                sequence: 0,
+                pid: 0,
+            },
+            0x10,
+            MessageFlags::empty(),
+            &[1u8; 256],
+        ));
+        let err = socket.receive_once().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EMSGSIZE));
+    }
+
+    #[test]
+    fn fake_kernel_no_reply() {
+        use crate::core::transport::{ack_message, MemoryTransport};
+        use crate::generic;
+
+        let transport = MemoryTransport::new();
+        transport.set_handler(|datagram| {
+            // Like the kernel, only reply when an acknowledge is requested
+            let mut replies = vec![];
+            let mut data = datagram;
+            while !data.is_empty() {
+                let header = Header::unpack(data).unwrap();
+                if header.flags().contains(MessageFlags::ACKNOWLEDGE) {
+                    replies.push(ack_message(&header));
+                }
+                data = &data[header.aligned_length()..];
+            }
+            replies
+        });
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        let msg = generic::Message::new(0x10u16, 3u8, MessageMode::None);
+        assert!(socket.transact(&msg).unwrap().is_empty());
+        let results = socket.transact_batch(&[&msg, &msg]).unwrap();
+        assert!(results.iter().all(|r| r.as_ref().unwrap().is_empty()));
+
+        let header = Header::unpack(&transport.sent()[0]).unwrap();
+        assert!(header.flags().contains(MessageFlags::ACKNOWLEDGE));
+        assert!(socket.receive_messages().unwrap().is_empty());
+    }
+
+    /// Message without a known size, which writes its payload directly
+    struct UnsizedMessage;
+
+    impl SendMessage for UnsizedMessage {
+        fn pack(&self, data: &mut [u8]) -> Result<usize> {
+            data[..5].copy_from_slice(&[1, 2, 3, 4, 5]);
+            Ok(5)
+        }
+        fn message_type(&self) -> u16 {
+            0x10
+        }
+        fn query_flags(&self) -> MessageFlags {
+            MessageFlags::REQUEST | MessageFlags::ACKNOWLEDGE
+        }
+    }
+
+    #[test]
+    fn send_batch_unsized() {
+        use crate::core::transport::MemoryTransport;
+
+        let transport = MemoryTransport::new();
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        let sequences = socket
+            .send_batch(&[&UnsizedMessage, &UnsizedMessage])
+            .unwrap();
+        assert_eq!(sequences, vec![1, 2]);
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        // Both messages are padded to an aligned size
+        assert_eq!(sent[0].len(), 2 * (Header::HEADER_SIZE + 8));
+        assert_eq!(Header::unpack(&sent[0]).unwrap().length, 21);
+        assert_eq!(&sent[0][16..24], &[1, 2, 3, 4, 5, 0, 0, 0]);
+        assert_eq!(&sent[0][40..48], &[1, 2, 3, 4, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn receive_message_refs() {
+        use crate::core::transport::{data_message, MemoryTransport};
+
+        let transport = MemoryTransport::new();
+        let mut socket = Socket::with_transport(transport.clone(), 200);
+        let notification = Header {
+            length: 0,
+            identifier: 0,
+            flags: 0,
+            sequence: 0,
+            pid: 0,
+        };
+        let mut payload = vec![0u8, 0, 0, 0];
+        let attribute = Attribute::new_string_with_nul(3u16, "eth0");
+        payload.resize(4 + attribute.pack_size() + 3, 0);
+        attribute.pack(&mut payload[4..]).unwrap();
+        let mut datagram = data_message(&notification, 16, MessageFlags::empty(), &payload);
+        datagram.extend(data_message(&notification, 17, MessageFlags::empty(), &[]));
+        transport.push(datagram);
+
+        let messages: Vec<_> = socket
+            .receive_message_refs()
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].identifier(), 16);
+        let attributes: Vec<_> = messages[0].attributes(4).collect();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].attribute_type(), 3);
+        assert_eq!(attributes[0].as_str().unwrap(), "eth0");
+        assert_eq!(messages[1].identifier(), 17);
+        assert!(messages[1].data().is_empty());
+
+        // Nothing pending, the fake kernel does not block
+        assert_eq!(socket.receive_message_refs().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn attach_filter() {
+        let mut receiver = Socket::new_multicast(Protocol::Usersock, &[3]).unwrap();
+        let filter = FilterBuilder::new().message_type(17).build().unwrap();
+        receiver.attach_filter(&filter).unwrap();
+        receiver.lock_filter().unwrap();
+        assert!(receiver.detach_filter().is_err());
+
+        broadcast_message(3, 16, 2);
+        broadcast_message(3, 17, 1);
+        broadcast_message(3, 16, 2);
+        let messages = receiver.receive_notifications().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].header.identifier, 17);
+        assert!(receiver.receive_notifications().unwrap().is_empty());
+    }
+
+    #[test]
+    fn attach_long_filter() {
+        let mut socket = Socket::new(Protocol::Usersock).unwrap();
+        let accept = FilterInstruction::new(0x06, 0, 0, u32::MAX);
+        for length in [libc::BPF_MAXINSNS as usize + 1, 65537] {
+            let filter = Filter::from_instructions(vec![accept; length]);
+            let err = socket.attach_filter(&filter).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        }
+        let filter = Filter::from_instructions(vec![accept; libc::BPF_MAXINSNS as usize]);
+        socket.attach_filter(&filter).unwrap();
+    }
+
+    #[test]
+    fn dump_interrupted() {
+        use crate::core::transport::{data_message, done_message, MemoryTransport};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // The first two dumps are interrupted
+        let transport = MemoryTransport::new();
+        let dumps = Arc::new(AtomicUsize::new(0));
+        let counter = dumps.clone();
+        transport.set_handler(move |request| {
+            let header = Header::unpack(request).unwrap();
+            let mut flags = MessageFlags::MULTIPART | MessageFlags::DUMP_FILTERED;
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                flags |= MessageFlags::DUMP_INTERRUPTED;
+            }
+            vec![
+                data_message(&header, 16, flags, &[0u8; 16]),
+                done_message(&header),
+            ]
+        });
+        let mut socket = Socket::with_transport(transport, 400);
+        let msg = route::Message::new(route::FamilyId::GetLink);
+
+        let mut iter = socket.dump(&msg).unwrap();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.is_filtered());
+        assert!(matches!(iter.next(), Some(Err(Error::DumpInterrupted))));
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        assert!(matches!(
+            socket.dump_consistent(&msg, 0),
+            Err(Error::DumpInterrupted)
+        ));
+        assert_eq!(socket.dump_consistent(&msg, 2).unwrap().len(), 1);
+        assert_eq!(dumps.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn create_with_echo() {
+        use crate::core::transport::{ack_message, data_message, error_message, MemoryTransport};
+        use crate::generic;
+
+        // Entries can be created once, the created entry is echoed back
+        let transport = MemoryTransport::new();
+        let mut created = false;
+        transport.set_handler(move |request| {
+            let header = Header::unpack(request).unwrap();
+            let flags = header.flags();
+            if created && flags.contains(MessageFlags::EXCL) {
+                return vec![error_message(&header, -libc::EEXIST)];
+            }
+            created = true;
+            let mut replies = vec![];
+            if flags.contains(MessageFlags::ECHO) {
+                let payload = &request[Header::HEADER_SIZE..];
+                replies.push(data_message(&header, 0x20, MessageFlags::empty(), payload));
+            }
+            // A reply to a request that was never sent
+            let mut unknown = header.clone();
+            unknown.sequence += 100;
+            replies.push(data_message(
+                &unknown,
+                0x20,
+                MessageFlags::empty(),
+                &[0u8; 4],
+            ));
+            replies.push(ack_message(&header));
+            replies
+        });
+        let mut socket = Socket::with_transport(transport, 500);
+        let flags = MessageFlags::from(MessageMode::Create) | MessageFlags::ECHO;
+        let msg = generic::Message::new(0x20u16, 1u8, flags);
+        let messages = socket.transact(&msg).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data[0], 1);
+        assert_eq!(socket.receive_notifications().unwrap().len(), 1);
+
+        let msg = generic::Message::new(0x20u16, 1u8, MessageMode::Create);
+        match socket.transact(&msg) {
+            Err(err) => assert_eq!(err.raw_os_error(), Some(libc::EEXIST)),
+            Ok(_) => panic!("expected an error"),
+        }
+        let msg = generic::Message::new(0x20u16, 1u8, MessageMode::Replace);
+        assert!(socket.transact(&msg).unwrap().is_empty());
     }
 }
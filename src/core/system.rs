@@ -1,9 +1,12 @@
 use libc;
 use std::io;
-use std::mem::size_of;
-use std::os::unix::io::RawFd;
+use std::mem::{size_of, size_of_val};
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::time::{Duration, Instant};
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Address {
     pub family: u16,
     pub _pad: u16,
@@ -25,12 +28,94 @@ pub fn get_page_size() -> usize {
     unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
 }
 
-pub fn netlink_socket(service: i32) -> io::Result<RawFd> {
-    Ok(ccall!(libc::socket(
-        libc::AF_NETLINK,
-        libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
-        service
-    )))
+pub fn netlink_socket(service: i32, nonblocking: bool) -> io::Result<OwnedFd> {
+    let mut kind = libc::SOCK_RAW | libc::SOCK_CLOEXEC;
+    if nonblocking {
+        kind |= libc::SOCK_NONBLOCK;
+    }
+    let fd = ccall!(libc::socket(libc::AF_NETLINK, kind, service));
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+pub fn is_nonblocking(socket: RawFd) -> io::Result<bool> {
+    let flags = ccall!(libc::fcntl(socket, libc::F_GETFL));
+    Ok(flags & libc::O_NONBLOCK != 0)
+}
+
+pub fn set_nonblocking(socket: RawFd, nonblocking: bool) -> io::Result<()> {
+    let flags = ccall!(libc::fcntl(socket, libc::F_GETFL));
+    let flags = if nonblocking {
+        flags | libc::O_NONBLOCK
+    } else {
+        flags & !libc::O_NONBLOCK
+    };
+    ccall!(libc::fcntl(socket, libc::F_SETFL, flags));
+    Ok(())
+}
+
+/// Set the receive timeout, None disables the timeout
+///
+/// The timeout is rounded up to whole microseconds, and to at least one
+/// microsecond, as a zero timeout disables the timeout.
+pub fn set_receive_timeout(socket: RawFd, timeout: Option<Duration>) -> io::Result<()> {
+    let micros = match timeout {
+        Some(timeout) => timeout.as_nanos().div_ceil(1000).max(1),
+        None => 0,
+    };
+    let value = libc::timeval {
+        tv_sec: (micros / 1_000_000) as libc::time_t,
+        tv_usec: (micros % 1_000_000) as libc::suseconds_t,
+    };
+    set_socket_option(socket, libc::SOL_SOCKET, libc::SO_RCVTIMEO, value)
+}
+
+/// Get the receive timeout, None if the timeout is disabled
+pub fn get_receive_timeout(socket: RawFd) -> io::Result<Option<Duration>> {
+    let mut value: libc::timeval = unsafe { std::mem::zeroed() };
+    let value_ptr: *mut libc::timeval = &mut value;
+    let mut value_len = size_of::<libc::timeval>() as libc::socklen_t;
+    ccall!(libc::getsockopt(
+        socket,
+        libc::SOL_SOCKET,
+        libc::SO_RCVTIMEO,
+        value_ptr as *mut libc::c_void,
+        &mut value_len
+    ));
+    let timeout = Duration::new(value.tv_sec as u64, value.tv_usec as u32 * 1000);
+    Ok(if timeout.is_zero() {
+        None
+    } else {
+        Some(timeout)
+    })
+}
+
+/// Wait for the socket to become readable, returns false if the timeout
+/// expired. None waits forever.
+///
+/// Waiting continues with the remaining time if interrupted by a signal.
+pub fn poll_read(socket: RawFd, timeout: Option<Duration>) -> io::Result<bool> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let mut fds = libc::pollfd {
+            fd: socket,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = deadline.map_or(-1, |deadline| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            // Round up, so that the wait does not end early
+            remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+        });
+        let count = unsafe { libc::poll(&mut fds, 1, timeout) };
+        if count < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        return Ok(count > 0);
+    }
 }
 
 pub fn set_socket_option<T: Sized>(
@@ -50,6 +135,64 @@ pub fn set_socket_option<T: Sized>(
     Ok(())
 }
 
+pub fn get_socket_option<T: Sized + Default>(
+    socket: RawFd,
+    level: i32,
+    name: i32,
+) -> io::Result<T> {
+    let mut value = T::default();
+    let value_ptr: *mut T = &mut value;
+    let mut value_len = size_of::<T>() as libc::socklen_t;
+    ccall!(libc::getsockopt(
+        socket,
+        level,
+        name,
+        value_ptr as *mut libc::c_void,
+        &mut value_len
+    ));
+    Ok(value)
+}
+
+/// Get a socket option consisting of an array of values
+///
+/// Returns the size in bytes of the option, which may be larger than the
+/// provided slice.
+pub fn get_socket_option_slice<T: Sized + Copy>(
+    socket: RawFd,
+    level: i32,
+    name: i32,
+    values: &mut [T],
+) -> io::Result<usize> {
+    let mut value_len = size_of_val(values) as libc::socklen_t;
+    ccall!(libc::getsockopt(
+        socket,
+        level,
+        name,
+        values.as_mut_ptr() as *mut libc::c_void,
+        &mut value_len
+    ));
+    Ok(value_len as usize)
+}
+
+/// Move the calling thread into the network namespace referred to by the
+/// file descriptor
+pub fn set_network_namespace(netns: RawFd) -> io::Result<()> {
+    ccall!(libc::setns(netns, libc::CLONE_NEWNET));
+    Ok(())
+}
+
+/// Set a socket buffer size, using the forcing option when permitted to go
+/// beyond the system limit
+pub fn set_buffer_size(socket: RawFd, name: i32, force: i32, size: usize) -> io::Result<()> {
+    let size = size.min(i32::MAX as usize) as i32;
+    match set_socket_option(socket, libc::SOL_SOCKET, force, size) {
+        Err(ref err) if err.raw_os_error() == Some(libc::EPERM) => {
+            set_socket_option(socket, libc::SOL_SOCKET, name, size)
+        }
+        result => result,
+    }
+}
+
 pub fn bind(socket: RawFd, address: &Address) -> io::Result<()> {
     let addr_ptr: *const Address = address;
     ccall!(libc::bind(
@@ -60,6 +203,16 @@ pub fn bind(socket: RawFd, address: &Address) -> io::Result<()> {
     Ok(())
 }
 
+pub fn connect(socket: RawFd, address: &Address) -> io::Result<()> {
+    let addr_ptr: *const Address = address;
+    ccall!(libc::connect(
+        socket,
+        addr_ptr as *const libc::sockaddr,
+        size_of::<Address>() as u32
+    ));
+    Ok(())
+}
+
 pub fn get_socket_address(socket: RawFd, address: &mut Address) -> io::Result<()> {
     let addr_ptr = address as *mut Address as *mut libc::sockaddr;
     let mut addr_len = size_of::<Address>() as libc::socklen_t;
@@ -72,15 +225,16 @@ pub fn send_message(socket: RawFd, header: &libc::msghdr, flags: i32) -> io::Res
     Ok(ccall!(libc::sendmsg(socket, header as *const libc::msghdr, flags)) as usize)
 }
 
-pub fn receive_message(socket: RawFd, header: &mut libc::msghdr) -> io::Result<usize> {
-    Ok(ccall!(libc::recvmsg(socket, header as *mut libc::msghdr, 0)) as usize)
+pub fn receive_message(socket: RawFd, header: &mut libc::msghdr, flags: i32) -> io::Result<usize> {
+    Ok(ccall!(libc::recvmsg(socket, header as *mut libc::msghdr, flags)) as usize)
 }
 
-pub fn send(socket: RawFd, data: &[u8], flags: i32) -> io::Result<usize> {
-    Ok(ccall!(libc::send(
+/// Get the size of the next pending datagram without receiving it
+pub fn peek_size(socket: RawFd) -> io::Result<usize> {
+    Ok(ccall!(libc::recv(
         socket,
-        data.as_ptr() as *const libc::c_void,
-        data.len(),
-        flags
+        ptr::null_mut(),
+        0,
+        libc::MSG_PEEK | libc::MSG_TRUNC
     )) as usize)
 }
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::io;
+use std::mem::{self, size_of};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use libc;
+
+use crate::core::ancillary::{ReceiveInfo, CONTROL_BUFFER_WORDS};
+use crate::core::message::{netlink_align, Header, MessageFlags};
+use crate::core::pack::NativePack;
+use crate::core::system;
+
+/// Transport of netlink datagrams
+///
+/// Used by [Socket](struct.Socket.html) to send and receive datagrams. The
+/// default transport is a netlink socket, see
+/// [MemoryTransport](struct.MemoryTransport.html) for testing without a
+/// kernel.
+pub trait Transport {
+    /// Send a single datagram made up of the provided buffers, returns the
+    /// number of bytes sent
+    fn send(&mut self, buffers: &[&[u8]]) -> io::Result<usize>;
+    /// Get the size of the next pending datagram without receiving it
+    ///
+    /// Fails with `io::ErrorKind::WouldBlock` if no datagram is pending and
+    /// the transport does not block.
+    fn peek_size(&mut self) -> io::Result<usize>;
+    /// Receive the next datagram into the buffer, returns the size of the
+    /// received data and information about the sender
+    ///
+    /// A datagram larger than the buffer is truncated.
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<(usize, ReceiveInfo)>;
+    /// Wait for a datagram to become pending, returns false if the timeout
+    /// expired. None waits forever.
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool>;
+}
+
+/// Transport using a netlink socket
+pub struct NetlinkTransport {
+    socket: OwnedFd,
+    sender: system::Address,
+    control_buffer: Vec<u64>,
+}
+
+impl NetlinkTransport {
+    pub(crate) fn new(socket: OwnedFd) -> NetlinkTransport {
+        NetlinkTransport {
+            socket,
+            sender: system::Address {
+                family: libc::AF_NETLINK as u16,
+                _pad: 0,
+                pid: 0,
+                groups: 0,
+            },
+            control_buffer: vec![0u64; CONTROL_BUFFER_WORDS],
+        }
+    }
+
+    fn message_header(&mut self, iov: &mut [libc::iovec]) -> libc::msghdr {
+        let addr_ptr = &mut self.sender as *mut system::Address;
+        let control_ptr = self.control_buffer.as_mut_ptr() as *mut libc::c_void;
+        let control_len = self.control_buffer.len() * size_of::<u64>();
+        let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+        hdr.msg_iovlen = iov.len() as _;
+        hdr.msg_iov = iov.as_mut_ptr();
+        hdr.msg_namelen = size_of::<system::Address>() as u32;
+        hdr.msg_name = addr_ptr as *mut libc::c_void;
+        hdr.msg_controllen = control_len as _;
+        hdr.msg_control = control_ptr;
+        hdr
+    }
+}
+
+impl Transport for NetlinkTransport {
+    fn send(&mut self, buffers: &[&[u8]]) -> io::Result<usize> {
+        let mut iov: Vec<libc::iovec> = buffers
+            .iter()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_ptr() as *mut libc::c_void,
+                iov_len: buffer.len(),
+            })
+            .collect();
+        let mut header: libc::msghdr = unsafe { mem::zeroed() };
+        header.msg_iov = iov.as_mut_ptr();
+        header.msg_iovlen = iov.len() as _;
+        system::send_message(self.socket.as_raw_fd(), &header, 0)
+    }
+
+    fn peek_size(&mut self) -> io::Result<usize> {
+        system::peek_size(self.socket.as_raw_fd())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<(usize, ReceiveInfo)> {
+        let mut iov = [libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buffer.len(),
+        }];
+        let mut header = self.message_header(&mut iov);
+        let bytes = system::receive_message(self.socket.as_raw_fd(), &mut header, 0)?;
+        Ok((bytes, ReceiveInfo::from_message_header(&header)))
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        system::poll_read(self.socket.as_raw_fd(), timeout)
+    }
+}
+
+impl AsRawFd for NetlinkTransport {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl AsFd for NetlinkTransport {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+impl IntoRawFd for NetlinkTransport {
+    fn into_raw_fd(self) -> RawFd {
+        self.socket.into_raw_fd()
+    }
+}
+
+impl From<NetlinkTransport> for OwnedFd {
+    fn from(transport: NetlinkTransport) -> OwnedFd {
+        transport.socket
+    }
+}
+
+type Handler = Box<dyn FnMut(&[u8]) -> Vec<Vec<u8>> + Send>;
+
+#[derive(Default)]
+struct MemoryState {
+    sent: Vec<Vec<u8>>,
+    pending: VecDeque<(Vec<u8>, ReceiveInfo)>,
+    handler: Option<Handler>,
+}
+
+/// In-memory transport which stands in for the kernel in tests
+///
+/// Datagrams sent by the socket are recorded, see `sent`. Replies are
+/// scripted either by queueing datagrams up front with `push`, or by
+/// installing a handler which is called for every sent datagram. The
+/// `data_message`, `done_message`, `error_message` and `ack_message`
+/// functions build replies for a request.
+///
+/// The transport is a handle to shared state, keep a clone to inspect and
+/// script it after handing it to
+/// [Socket::with_transport](struct.Socket.html#method.with_transport).
+/// Waiting for a datagram when none is queued fails with
+/// `io::ErrorKind::WouldBlock`, instead of blocking forever.
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryTransport {
+    /// Create a new MemoryTransport
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Queue a datagram to be received, as if sent by the kernel
+    pub fn push(&self, datagram: Vec<u8>) {
+        self.push_with_info(datagram, ReceiveInfo::default());
+    }
+
+    /// Queue a datagram to be received, with the provided sender information
+    pub fn push_with_info(&self, datagram: Vec<u8>, info: ReceiveInfo) {
+        self.lock().pending.push_back((datagram, info));
+    }
+
+    /// Set a handler producing the reply datagrams for every sent datagram
+    pub fn set_handler<F>(&self, handler: F)
+    where
+        F: FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        self.lock().handler = Some(Box::new(handler));
+    }
+
+    /// Take the datagrams sent so far
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.lock().sent)
+    }
+
+    /// Get the number of datagrams waiting to be received
+    pub fn pending(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, buffers: &[&[u8]]) -> io::Result<usize> {
+        let datagram = buffers.concat();
+        let size = datagram.len();
+        let mut state = self.lock();
+        if let Some(ref mut handler) = state.handler {
+            let replies = handler(&datagram);
+            for reply in replies {
+                state.pending.push_back((reply, ReceiveInfo::default()));
+            }
+        }
+        state.sent.push(datagram);
+        Ok(size)
+    }
+
+    fn peek_size(&mut self) -> io::Result<usize> {
+        match self.lock().pending.front() {
+            Some((datagram, _)) => Ok(datagram.len()),
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> io::Result<(usize, ReceiveInfo)> {
+        match self.lock().pending.pop_front() {
+            Some((datagram, info)) => {
+                let size = datagram.len().min(buffer.len());
+                buffer[..size].copy_from_slice(&datagram[..size]);
+                Ok((size, info))
+            }
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn wait(&mut self, _timeout: Option<Duration>) -> io::Result<bool> {
+        if self.lock().pending.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(true)
+    }
+}
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+/// Build a datagram holding a single message
+fn message(identifier: u16, flags: MessageFlags, request: &Header, payload: &[u8]) -> Vec<u8> {
+    let size = Header::HEADER_SIZE + payload.len();
+    let mut data = vec![0u8; netlink_align(size)];
+    let header = Header {
+        length: size as u32,
+        identifier,
+        flags: flags.bits(),
+        sequence: request.sequence,
+        pid: request.pid,
+    };
+    header.pack_unchecked(&mut data);
+    data[Header::HEADER_SIZE..size].copy_from_slice(payload);
+    data
+}
+
+/// Build a reply data message for the request
+///
+/// Concatenate several with the `MULTIPART` flag set, followed by a
+/// `done_message`, to reply with a dump.
+pub fn data_message(
+    request: &Header,
+    identifier: u16,
+    flags: MessageFlags,
+    payload: &[u8],
+) -> Vec<u8> {
+    message(identifier, flags, request, payload)
+}
+
+/// Build the `NLMSG_DONE` message ending a dump
+pub fn done_message(request: &Header) -> Vec<u8> {
+    message(
+        NLMSG_DONE,
+        MessageFlags::MULTIPART,
+        request,
+        &0i32.to_ne_bytes(),
+    )
+}
+
+/// Build an error message for the request, with a negative errno value
+///
+/// The request header is included in the error message, as when
+/// `NETLINK_CAP_ACK` is set.
+pub fn error_message(request: &Header, code: i32) -> Vec<u8> {
+    let mut payload = vec![0u8; 4 + Header::HEADER_SIZE];
+    payload[..4].copy_from_slice(&code.to_ne_bytes());
+    request.pack_unchecked(&mut payload[4..]);
+    message(NLMSG_ERROR, MessageFlags::CAPPED, request, &payload)
+}
+
+/// Build an acknowledge message for the request
+pub fn ack_message(request: &Header) -> Vec<u8> {
+    error_message(request, 0)
+}
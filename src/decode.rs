@@ -0,0 +1,506 @@
+//! Human readable decoding of netlink messages
+//!
+//! Walks a message using the known attribute schemas and prints it as an
+//! indented tree, similar to the netlink decoding of strace. Attributes
+//! without a schema are printed as hex dumps.
+//!
+//! ```no_run
+//! use netlink_rust::decode;
+//! use netlink_rust::route;
+//! use netlink_rust::{Protocol, Socket};
+//!
+//! let mut socket = Socket::new(Protocol::Route).unwrap();
+//! let request = route::Message::new(route::FamilyId::GetLink);
+//! for message in socket.transact(&request).unwrap() {
+//!     println!("{}", decode::decode(Protocol::Route, &message));
+//! }
+//! ```
+
+use std::fmt;
+use std::io;
+
+use crate::core::{Attribute, ConvertFrom, Message, MessageFlags, NativeUnpack, Protocol};
+use crate::generic;
+use crate::route::{self, InterfaceInformationMessage};
+
+const NLMSG_NOOP: u16 = 1;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLMSG_OVERRUN: u16 = 4;
+
+/// Value type of an attribute
+#[derive(Clone, Copy)]
+pub enum Kind {
+    /// Raw bytes, printed as a hex dump
+    Bytes,
+    /// String, optionally NUL terminated
+    String,
+    /// Unsigned 8 bit integer
+    U8,
+    /// Unsigned 16 bit integer
+    U16,
+    /// Unsigned 32 bit integer
+    U32,
+    /// Unsigned 64 bit integer
+    U64,
+    /// Signed 32 bit integer
+    I32,
+    /// Hardware address
+    HardwareAddress,
+    /// Nested attributes
+    Nested(Schema),
+    /// Array of nested attributes, each element is a nested attribute with
+    /// the index as identifier
+    NestedArray(Schema),
+}
+
+/// Attribute of a schema
+pub struct Field {
+    /// Attribute identifier
+    pub identifier: u16,
+    /// Attribute name
+    pub name: &'static str,
+    /// Attribute value type
+    pub kind: Kind,
+}
+
+/// Names and value types of the attributes of a message or nest
+#[derive(Clone, Copy)]
+pub enum Schema {
+    /// Attributes listed as fields
+    Fields(&'static [Field]),
+    /// Attributes with an attribute enum, gives the name and value type of
+    /// an attribute identifier
+    Lookup(fn(u16) -> Option<(String, Kind)>),
+}
+
+impl Schema {
+    fn find(&self, identifier: u16) -> Option<(String, Kind)> {
+        match *self {
+            Schema::Fields(fields) => fields
+                .iter()
+                .find(|field| field.identifier == identifier)
+                .map(|field| (field.name.to_string(), field.kind)),
+            Schema::Lookup(lookup) => lookup(identifier),
+        }
+    }
+}
+
+macro_rules! fields {
+    ( $( $id:expr => $name:ident : $kind:expr ),* $(,)* ) => (
+        Schema::Fields(&[ $( Field { identifier: $id, name: stringify!($name), kind: $kind }, )* ])
+    );
+}
+
+/// Name an attribute by its attribute enum
+fn named<T: ConvertFrom<u16> + fmt::Debug>(
+    identifier: u16,
+    kind: fn(&T) -> Kind,
+) -> Option<(String, Kind)> {
+    T::convert_from(identifier).map(|attribute| (format!("{:?}", attribute), kind(&attribute)))
+}
+
+/// Link information attributes, `IFLA_INFO_*`
+pub static LINK_INFO_ATTRIBUTES: Schema = fields!(
+    1 => IFLA_INFO_KIND: Kind::String,
+    2 => IFLA_INFO_DATA: Kind::Bytes,
+    3 => IFLA_INFO_XSTATS: Kind::Bytes,
+    4 => IFLA_INFO_SLAVE_KIND: Kind::String,
+    5 => IFLA_INFO_SLAVE_DATA: Kind::Bytes,
+);
+
+/// Link XDP attributes, `IFLA_XDP_*`
+pub static LINK_XDP_ATTRIBUTES: Schema = fields!(
+    1 => IFLA_XDP_FD: Kind::I32,
+    2 => IFLA_XDP_ATTACHED: Kind::U8,
+    3 => IFLA_XDP_FLAGS: Kind::U32,
+    4 => IFLA_XDP_PROG_ID: Kind::U32,
+);
+
+/// Link attributes, `IFLA_*`, named by
+/// [AddressFamilyAttribute](../route/enum.AddressFamilyAttribute.html)
+pub static LINK_ATTRIBUTES: Schema = Schema::Lookup(|identifier| named(identifier, link_kind));
+
+fn link_kind(attribute: &route::AddressFamilyAttribute) -> Kind {
+    use crate::route::AddressFamilyAttribute::*;
+    match *attribute {
+        Address | Broadcast => Kind::HardwareAddress,
+        InterfaceName | QDisc | InterfaceAlias | PhysPortName => Kind::String,
+        MTU
+        | Link
+        | Master
+        | TransmitQueueLength
+        | Weight
+        | NetworkNameSpacePid
+        | NumberVf
+        | Group
+        | NetworkNameSpaceFileDescriptor
+        | ExtendedMask
+        | PromiscuityCount
+        | TransmitQueueCount
+        | ReceiveQueueCount
+        | CarrierChanges
+        | GsoMaximumSegs
+        | GsoMaximumSize
+        | Event => Kind::U32,
+        OperationState | LinkMode | Carrier | ProtocolDown => Kind::U8,
+        LinkNetworkNameSpaceId | NewNetworkNameSpaceId | InterfaceNetworkNameSpaceId => Kind::I32,
+        LinkInfo => Kind::Nested(LINK_INFO_ATTRIBUTES),
+        Xdp => Kind::Nested(LINK_XDP_ATTRIBUTES),
+        _ => Kind::Bytes,
+    }
+}
+
+/// Generic netlink control operation attributes, named by
+/// [OperationAttributeId](../generic/enum.OperationAttributeId.html)
+pub static CONTROL_OPERATION_ATTRIBUTES: Schema =
+    Schema::Lookup(|identifier| named(identifier, control_operation_kind));
+
+fn control_operation_kind(attribute: &generic::OperationAttributeId) -> Kind {
+    match *attribute {
+        generic::OperationAttributeId::Unspecified => Kind::Bytes,
+        generic::OperationAttributeId::Id | generic::OperationAttributeId::Flags => Kind::U32,
+    }
+}
+
+/// Generic netlink control multicast group attributes, named by
+/// [MulticastAttributeId](../generic/enum.MulticastAttributeId.html)
+pub static CONTROL_MULTICAST_ATTRIBUTES: Schema =
+    Schema::Lookup(|identifier| named(identifier, control_multicast_kind));
+
+fn control_multicast_kind(attribute: &generic::MulticastAttributeId) -> Kind {
+    match *attribute {
+        generic::MulticastAttributeId::Unspecified => Kind::Bytes,
+        generic::MulticastAttributeId::Name => Kind::String,
+        generic::MulticastAttributeId::Id => Kind::U32,
+    }
+}
+
+/// Generic netlink control attributes, named by
+/// [AttributeId](../generic/enum.AttributeId.html)
+pub static CONTROL_ATTRIBUTES: Schema =
+    Schema::Lookup(|identifier| named(identifier, control_kind));
+
+fn control_kind(attribute: &generic::AttributeId) -> Kind {
+    use crate::generic::AttributeId::*;
+    match *attribute {
+        Unspecified => Kind::Bytes,
+        FamilyId => Kind::U16,
+        FamilyName => Kind::String,
+        Version | HeaderSize | MaximumAttributes => Kind::U32,
+        Operations => Kind::NestedArray(CONTROL_OPERATION_ATTRIBUTES),
+        MulticastGroups => Kind::NestedArray(CONTROL_MULTICAST_ATTRIBUTES),
+    }
+}
+
+/// A message decoded for display, see `decode`
+pub struct Decoded<'a> {
+    protocol: Protocol,
+    message: &'a Message,
+}
+
+/// Decode a message of the netlink protocol for display
+///
+/// The protocol selects the schemas, message types are only unique within a
+/// protocol.
+pub fn decode(protocol: Protocol, message: &Message) -> Decoded<'_> {
+    Decoded { protocol, message }
+}
+
+impl<'a> fmt::Display for Decoded<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = &self.message.header;
+        let data = &self.message.data[..];
+        write!(f, "{}", type_name(self.protocol.clone(), header.identifier))?;
+        write!(f, " length={} flags=", header.length)?;
+        write_flags(f, header.identifier, header.flags)?;
+        write!(f, " sequence={} pid={}", header.sequence, header.pid)?;
+        match header.identifier {
+            NLMSG_NOOP | NLMSG_OVERRUN => Ok(()),
+            NLMSG_ERROR | NLMSG_DONE if data.len() >= 4 => {
+                let code = i32::unpack_unchecked(data);
+                write!(f, "\n  error={}", code)?;
+                if code < 0 {
+                    write!(f, " ({})", io::Error::from_raw_os_error(-code))?;
+                }
+                write_hex(f, &data[4..], 1)
+            }
+            identifier => match self.protocol {
+                Protocol::Route if route::FamilyId::convert_from(identifier).is_some() => {
+                    write_link(f, data)
+                }
+                Protocol::Generic => write_generic(f, data, identifier),
+                _ => write_hex(f, data, 1),
+            },
+        }
+    }
+}
+
+fn type_name(protocol: Protocol, identifier: u16) -> String {
+    let name = match identifier {
+        NLMSG_NOOP => Some("Noop".to_string()),
+        NLMSG_ERROR => Some("Error".to_string()),
+        NLMSG_DONE => Some("Done".to_string()),
+        NLMSG_OVERRUN => Some("Overrun".to_string()),
+        _ => match protocol {
+            Protocol::Route => {
+                route::FamilyId::convert_from(identifier).map(|v| format!("{:?}", v))
+            }
+            Protocol::Generic => {
+                generic::FamilyId::convert_from(identifier).map(|v| format!("{:?}", v))
+            }
+            _ => None,
+        },
+    };
+    match name {
+        Some(name) => format!("{} ({})", name, identifier),
+        None => format!("{}", identifier),
+    }
+}
+
+const COMMON_FLAGS: &[(&str, MessageFlags)] = &[
+    ("REQUEST", MessageFlags::REQUEST),
+    ("MULTIPART", MessageFlags::MULTIPART),
+    ("ACKNOWLEDGE", MessageFlags::ACKNOWLEDGE),
+    ("ECHO", MessageFlags::ECHO),
+    ("DUMP_INTERRUPTED", MessageFlags::DUMP_INTERRUPTED),
+    ("DUMP_FILTERED", MessageFlags::DUMP_FILTERED),
+];
+
+const GET_FLAGS: &[(&str, MessageFlags)] = &[
+    ("DUMP", MessageFlags::DUMP),
+    ("ROOT", MessageFlags::ROOT),
+    ("MATCH", MessageFlags::MATCH),
+    ("ATOMIC", MessageFlags::ATOMIC),
+];
+
+const NEW_FLAGS: &[(&str, MessageFlags)] = &[
+    ("REPLACE", MessageFlags::REPLACE),
+    ("EXCL", MessageFlags::EXCL),
+    ("CREATE", MessageFlags::CREATE),
+    ("APPEND", MessageFlags::APPEND),
+];
+
+const ACKNOWLEDGE_FLAGS: &[(&str, MessageFlags)] = &[
+    ("CAPPED", MessageFlags::CAPPED),
+    ("ACK_TLVS", MessageFlags::ACK_TLVS),
+];
+
+fn write_flags(f: &mut fmt::Formatter, identifier: u16, flags: u16) -> fmt::Result {
+    // The upper byte of the flags depends on the kind of message
+    let value = MessageFlags::from_bits_retain(flags);
+    let upper = if identifier == NLMSG_ERROR || identifier == NLMSG_DONE {
+        ACKNOWLEDGE_FLAGS
+    } else if value.intersects(MessageFlags::CREATE | MessageFlags::APPEND)
+        && !value.contains(MessageFlags::DUMP)
+    {
+        NEW_FLAGS
+    } else {
+        GET_FLAGS
+    };
+    let mut separator = "";
+    let mut unknown = flags;
+    for (name, flag) in COMMON_FLAGS.iter().chain(upper) {
+        if value.contains(*flag) && unknown & flag.bits() == flag.bits() {
+            write!(f, "{}{}", separator, name)?;
+            separator = "|";
+            unknown &= !flag.bits();
+        }
+    }
+    if unknown != 0 || flags == 0 {
+        write!(f, "{}{:#x}", separator, unknown)?;
+    }
+    Ok(())
+}
+
+fn write_link(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    match InterfaceInformationMessage::unpack(data) {
+        Ok((_, link)) => {
+            write!(
+                f,
+                "\n  family={} type={} index={} flags={:#x} change={:#x}",
+                link.family, link.kind, link.index, link.flags, link.change
+            )?;
+            write_attributes(f, &link.attributes, LINK_ATTRIBUTES, 1)
+        }
+        Err(_) => write_hex(f, data, 1),
+    }
+}
+
+fn write_generic(f: &mut fmt::Formatter, data: &[u8], identifier: u16) -> fmt::Result {
+    if data.len() < 4 {
+        return write_hex(f, data, 1);
+    }
+    // Only the commands of the control family are known
+    let control = identifier == generic::FamilyId::Control;
+    let (command, schema) = match generic::Command::convert_from(data[0]) {
+        Some(command) if control => (format!("{:?} ({})", command, data[0]), CONTROL_ATTRIBUTES),
+        _ => (format!("{}", data[0]), Schema::Fields(&[])),
+    };
+    write!(f, "\n  command={} version={}", command, data[1])?;
+    let (_, attributes) = Attribute::unpack_all(&data[4..]);
+    write_attributes(f, &attributes, schema, 1)
+}
+
+/// Write the attributes as a tree, using the schema to name and decode them
+pub fn write_attributes(
+    f: &mut fmt::Formatter,
+    attributes: &[Attribute],
+    schema: Schema,
+    depth: usize,
+) -> fmt::Result {
+    for attribute in attributes {
+        let identifier = attribute.attribute_type();
+        write!(f, "\n{:1$}", "", depth * 2)?;
+        let kind = match schema.find(identifier) {
+            Some((name, kind)) => {
+                write!(f, "{} ({}):", name, identifier)?;
+                kind
+            }
+            None => {
+                write!(f, "{}:", identifier)?;
+                Kind::Bytes
+            }
+        };
+        write_value(f, attribute, kind, depth)?;
+    }
+    Ok(())
+}
+
+fn write_value(
+    f: &mut fmt::Formatter,
+    attribute: &Attribute,
+    kind: Kind,
+    depth: usize,
+) -> fmt::Result {
+    let result = match kind {
+        Kind::String => attribute.as_string().map(|v| format!("{:?}", v)),
+        Kind::U8 => attribute.as_u8().map(|v| v.to_string()),
+        Kind::U16 => attribute.as_u16().map(|v| v.to_string()),
+        Kind::U32 => attribute.as_u32().map(|v| v.to_string()),
+        Kind::U64 => attribute.as_u64().map(|v| v.to_string()),
+        Kind::I32 => attribute.as_i32().map(|v| v.to_string()),
+        Kind::HardwareAddress if attribute.len() == 6 => {
+            attribute.as_hardware_address().map(|v| v.to_string())
+        }
+        Kind::Nested(schema) => {
+            let (_, nested) = Attribute::unpack_all(&attribute.as_bytes());
+            return write_attributes(f, &nested, schema, depth + 1);
+        }
+        Kind::NestedArray(schema) => {
+            let (_, elements) = Attribute::unpack_all(&attribute.as_bytes());
+            for element in elements {
+                let index = element.attribute_type();
+                write!(f, "\n{:2$}[{}]", "", index, (depth + 1) * 2)?;
+                let (_, nested) = Attribute::unpack_all(&element.as_bytes());
+                write_attributes(f, &nested, schema, depth + 2)?;
+            }
+            return Ok(());
+        }
+        _ => return write_hex(f, &attribute.as_bytes(), depth + 1),
+    };
+    match result {
+        Ok(value) => write!(f, " {}", value),
+        Err(_) => write_hex(f, &attribute.as_bytes(), depth + 1),
+    }
+}
+
+/// Write the data as a hex dump, 16 bytes per line
+fn write_hex(f: &mut fmt::Formatter, data: &[u8], depth: usize) -> fmt::Result {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        write!(f, "\n{:2$}{:04x}:", "", line * 16, depth * 2)?;
+        for byte in chunk {
+            write!(f, " {:02x}", byte)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Header, NativePack};
+
+    fn message(identifier: u16, flags: u16, data: Vec<u8>) -> Message {
+        Message {
+            header: Header {
+                length: (16 + data.len()) as u32,
+                identifier,
+                flags,
+                sequence: 7,
+                pid: 42,
+            },
+            data,
+        }
+    }
+
+    fn attributes(attributes: &[Attribute]) -> Vec<u8> {
+        let mut data = vec![];
+        for attribute in attributes {
+            let mut buffer = vec![0u8; (attribute.total_len() + 3) & !3];
+            attribute.pack(&mut buffer).unwrap();
+            data.extend(buffer);
+        }
+        data
+    }
+
+    #[test]
+    fn decode_link() {
+        let mut data = vec![0u8; 16];
+        data[4..8].copy_from_slice(&1i32.to_ne_bytes());
+        data.extend(attributes(&[
+            Attribute::new_string_with_nul(3u16, "lo"),
+            Attribute::new(4u16, 65536u32),
+            Attribute::new_bytes(99u16, &[1, 2, 3]),
+        ]));
+        let msg = message(16, 0x2, data);
+        assert_eq!(
+            decode(Protocol::Route, &msg).to_string(),
+            "NewLink (16) length=56 flags=MULTIPART sequence=7 pid=42\n  \
+             family=0 type=0 index=1 flags=0x0 change=0x0\n  \
+             InterfaceName (3): \"lo\"\n  \
+             MTU (4): 65536\n  \
+             99:\n    0000: 01 02 03"
+        );
+    }
+
+    #[test]
+    fn decode_control() {
+        let group = attributes(&[
+            Attribute::new_string_with_nul(1u16, "config"),
+            Attribute::new(2u16, 5u32),
+        ]);
+        let groups = attributes(&[Attribute::new_bytes(1u16, &group)]);
+        let mut data = vec![1u8, 2, 0, 0];
+        data.extend(attributes(&[
+            Attribute::new_string_with_nul(2u16, "nl80211"),
+            Attribute::new_bytes(7u16 | 0x8000, &groups),
+        ]));
+        let msg = message(16, 0, data);
+        assert_eq!(
+            decode(Protocol::Generic, &msg).to_string(),
+            format!(
+                "Control (16) length={} flags=0x0 sequence=7 pid=42\n  \
+                 command=NewFamily (1) version=2\n  \
+                 FamilyName (2): \"nl80211\"\n  \
+                 MulticastGroups (7):\n    \
+                 [1]\n      \
+                 Name (1): \"config\"\n      \
+                 Id (2): 5",
+                msg.header.length
+            )
+        );
+    }
+
+    #[test]
+    fn decode_error() {
+        let mut data = (-libc::ENOENT).to_ne_bytes().to_vec();
+        data.extend_from_slice(&[0u8; 16]);
+        let msg = message(2, 0x100, data);
+        let text = decode(Protocol::Generic, &msg).to_string();
+        assert!(
+            text.starts_with("Error (2) length=36 flags=CAPPED sequence=7 pid=42\n  error=-2 (")
+        );
+        assert!(text.ends_with("\n  0000: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00"));
+    }
+}
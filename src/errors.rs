@@ -5,6 +5,8 @@ use std::result;
 use std::str;
 use std::string;
 
+use crate::core::ExtendedAck;
+
 #[derive(Debug)]
 pub enum NetlinkErrorKind {
     NotEnoughData,
@@ -47,6 +49,50 @@ pub enum Error {
     FromUtf8(string::FromUtf8Error),
     /// A Netlink transport error has occured
     Netlink(NetlinkError),
+    /// The kernel reported an error with extended acknowledge information
+    ExtendedAck(Box<ExtendedAck>),
+    /// The socket receive buffer overran and messages were dropped
+    ///
+    /// Reported when the kernel fails to deliver messages to the socket,
+    /// `ENOBUFS`, or when receiving an `NLMSG_OVERRUN` message. The socket
+    /// stays usable, but any state tracked from notifications should be
+    /// re-synchronized, for example by dumping it again.
+    Overrun,
+    /// The dumped table changed during the dump, `NLM_F_DUMP_INTR`
+    ///
+    /// The replies are not a consistent snapshot, repeat the dump. See
+    /// [Socket::dump_consistent](struct.Socket.html#method.dump_consistent).
+    DumpInterrupted,
+    /// A required attribute is missing
+    ///
+    /// See [AttributeSet::require](core/struct.AttributeSet.html#method.require).
+    MissingAttribute {
+        /// Attribute type
+        identifier: u16,
+        /// Name of the attribute type
+        name: String,
+    },
+    /// An attribute does not follow its policy rule
+    ///
+    /// See [AttributeSet::validate](core/struct.AttributeSet.html#method.validate).
+    InvalidAttribute {
+        /// Attribute type
+        identifier: u16,
+        /// Name of the attribute type
+        name: String,
+    },
+}
+
+impl Error {
+    /// Get the OS error code, if the error was reported by the OS or kernel
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Error::Io(ref err) => err.raw_os_error(),
+            Error::ExtendedAck(ref ack) => Some(-ack.code),
+            Error::Overrun => Some(libc::ENOBUFS),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -56,6 +102,17 @@ impl fmt::Display for Error {
             Error::Utf8(ref err) => write!(f, "UTF8 error: {}", err),
             Error::FromUtf8(ref err) => write!(f, "From UTF8 error: {}", err),
             Error::Netlink(ref err) => write!(f, "Pack error: {}", err),
+            Error::ExtendedAck(ref ack) => write!(f, "Kernel error: {}", ack),
+            Error::Overrun => write!(f, "Overrun: messages were dropped"),
+            Error::DumpInterrupted => write!(f, "Dump interrupted: the result is inconsistent"),
+            Error::MissingAttribute {
+                identifier,
+                ref name,
+            } => write!(f, "Missing attribute {} ({})", name, identifier),
+            Error::InvalidAttribute {
+                identifier,
+                ref name,
+            } => write!(f, "Invalid attribute {} ({})", name, identifier),
         }
     }
 }
@@ -67,6 +124,11 @@ impl error::Error for Error {
             Error::Utf8(ref err) => Some(err),
             Error::FromUtf8(ref err) => Some(err),
             Error::Netlink(ref err) => Some(err),
+            Error::ExtendedAck(_)
+            | Error::Overrun
+            | Error::DumpInterrupted
+            | Error::MissingAttribute { .. }
+            | Error::InvalidAttribute { .. } => None,
         }
     }
 }
@@ -7,7 +7,9 @@ use std::convert::{From, Into};
 use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
 
 use crate::core;
-use crate::core::{Attribute, ConvertFrom, MessageFlags, MessageMode, NativePack, SendMessage};
+use crate::core::{
+    Attribute, AttributeSet, ConvertFrom, MessageFlags, MessageMode, NativePack, SendMessage,
+};
 
 extended_enum!(FamilyId, u16,
     Control => 16,
@@ -130,6 +132,9 @@ impl SendMessage for Message {
     fn query_flags(&self) -> MessageFlags {
         self.flags
     }
+    fn pack_size(&self) -> usize {
+        4 + core::attributes_pack_size(&self.attributes)
+    }
 }
 
 impl fmt::Display for Message {
@@ -158,18 +163,17 @@ pub struct MulticastGroup {
 }
 
 impl MulticastGroup {
-    fn from_bytes(bytes: &[u8]) -> Result<MulticastGroup> {
-        let (_, attributes) = core::Attribute::unpack_all(bytes);
+    fn from_attributes(attributes: core::AttributeIter<'_>) -> Result<MulticastGroup> {
         let mut group_name = String::new();
         let mut group_id = None;
         for attribute in attributes {
-            match MulticastAttributeId::from(attribute.identifier) {
+            match MulticastAttributeId::from(attribute.attribute_type()) {
                 MulticastAttributeId::Unspecified => {}
                 MulticastAttributeId::Id => {
                     group_id = attribute.as_u32().ok();
                 }
                 MulticastAttributeId::Name => {
-                    group_name = attribute.as_string()?;
+                    group_name = String::from(attribute.as_str()?);
                 }
             }
         }
@@ -204,25 +208,15 @@ pub struct Family {
 
 impl Family {
     fn from_message(message: Message) -> Result<Family> {
-        let mut family_name = String::new();
-        let mut family_id = 0u16;
+        let attributes = AttributeSet::<AttributeId>::new(message.attributes);
+        let family_id = attributes.require::<u16>(AttributeId::FamilyId)?;
+        let family_name = attributes
+            .get::<String>(AttributeId::FamilyName)?
+            .unwrap_or_default();
         let mut groups = vec![];
-        for attr in message.attributes {
-            match AttributeId::from(attr.identifier) {
-                AttributeId::Unspecified => {}
-                AttributeId::FamilyName => {
-                    family_name = attr.as_string()?;
-                }
-                AttributeId::FamilyId => {
-                    family_id = attr.as_u16()?;
-                }
-                AttributeId::MulticastGroups => {
-                    let (_, mcs_attributes) = core::Attribute::unpack_all(&attr.as_bytes());
-                    for mcs_attr in mcs_attributes {
-                        groups.push(MulticastGroup::from_bytes(&mcs_attr.as_bytes())?);
-                    }
-                }
-                _ => {}
+        if let Some(attr) = attributes.attribute(AttributeId::MulticastGroups) {
+            for mcs_attr in attr.nested() {
+                groups.push(MulticastGroup::from_attributes(mcs_attr.nested())?);
             }
         }
         if family_id > 0 {
@@ -237,7 +231,7 @@ impl Family {
 
     /// Request family with the provided name
     pub fn from_name(socket: &mut core::Socket, name: &str) -> Result<Family> {
-        {
+        let messages = {
             let mut tx_msg = Message::new(
                 FamilyId::Control,
                 Command::GetFamily,
@@ -247,20 +241,14 @@ impl Family {
                 AttributeId::FamilyName,
                 name,
             ));
-            socket.send_message(&tx_msg)?;
-        }
-        loop {
-            let messages = socket.receive_messages()?;
-            if messages.is_empty() {
-                break;
-            }
-            for m in messages {
-                if FamilyId::convert_from(m.header.identifier) == Some(FamilyId::Control) {
-                    let (_, msg) = Message::unpack(&m.data)?;
-                    let family = Family::from_message(msg)?;
-                    if family.name == name {
-                        return Ok(family);
-                    }
+            socket.transact(&tx_msg)?
+        };
+        for m in messages {
+            if FamilyId::convert_from(m.header.identifier) == Some(FamilyId::Control) {
+                let (_, msg) = Message::unpack(&m.data)?;
+                let family = Family::from_message(msg)?;
+                if family.name == name {
+                    return Ok(family);
                 }
             }
         }
@@ -270,7 +258,7 @@ impl Family {
     /// Request family with the provided identifier
     pub fn from_id<ID: Into<u16>>(socket: &mut core::Socket, id: ID) -> Result<Family> {
         let id = id.into().clone();
-        {
+        let messages = {
             let mut tx_msg = Message::new(
                 FamilyId::Control,
                 Command::GetFamily,
@@ -279,20 +267,14 @@ impl Family {
             tx_msg
                 .attributes
                 .push(Attribute::new(AttributeId::FamilyId, id));
-            socket.send_message(&tx_msg)?;
-        }
-        loop {
-            let messages = socket.receive_messages()?;
-            if messages.is_empty() {
-                break;
-            }
-            for m in messages {
-                if FamilyId::convert_from(m.header.identifier) == Some(FamilyId::Control) {
-                    let (_, msg) = Message::unpack(&m.data)?;
-                    let family = Family::from_message(msg)?;
-                    if family.id == id {
-                        return Ok(family);
-                    }
+            socket.transact(&tx_msg)?
+        };
+        for m in messages {
+            if FamilyId::convert_from(m.header.identifier) == Some(FamilyId::Control) {
+                let (_, msg) = Message::unpack(&m.data)?;
+                let family = Family::from_message(msg)?;
+                if family.id == id {
+                    return Ok(family);
                 }
             }
         }
@@ -301,13 +283,10 @@ impl Family {
 
     /// Request all famelies
     pub fn all(socket: &mut core::Socket) -> Result<Vec<Family>> {
-        {
-            let tx_msg = Message::new(FamilyId::Control, Command::GetFamily, MessageMode::Dump);
-            socket.send_message(&tx_msg)?;
-        }
-        let messages = socket.receive_messages()?;
+        let tx_msg = Message::new(FamilyId::Control, Command::GetFamily, MessageMode::Dump);
         let mut families = vec![];
-        for m in messages {
+        for m in socket.dump(&tx_msg)? {
+            let m = m?;
             if FamilyId::from(m.header.identifier) == FamilyId::Control {
                 let (_, msg) = Message::unpack(&m.data)?;
                 families.push(Family::from_message(msg)?);
@@ -326,7 +305,91 @@ impl fmt::Display for Family {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::{Protocol, ReceiveMode, Socket};
+    use crate::errors::Error;
     use libc;
+    use std::time::Duration;
+
+    #[test]
+    fn all_families() {
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        let families = Family::all(&mut socket).unwrap();
+        assert!(families.iter().any(|f| f.name == "nlctrl"));
+        let mode = ReceiveMode::Timeout(Duration::from_secs(1));
+        let mut socket = Socket::new_with_mode(Protocol::Generic, &[], mode).unwrap();
+        let family = Family::from_name(&mut socket, "nlctrl").unwrap();
+        assert_eq!(family.id, u16::from(FamilyId::Control));
+    }
+
+    fn get_family_message(name: &str) -> Message {
+        let mut msg = Message::new(
+            FamilyId::Control,
+            Command::GetFamily,
+            MessageMode::Acknowledge,
+        );
+        msg.append_attribute(Attribute::new_string_with_nul(
+            AttributeId::FamilyName,
+            name,
+        ));
+        msg
+    }
+
+    #[test]
+    fn correlated_requests() {
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        let first = socket.send_message(&get_family_message("nlctrl")).unwrap();
+        let second = socket
+            .send_message(&get_family_message("no-such-family"))
+            .unwrap();
+        assert_ne!(first, second);
+        assert!(socket.receive_for(second).is_err());
+        let messages = socket.receive_for(first).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].header.sequence, first);
+        assert!(socket.receive_for(first).is_err());
+    }
+
+    #[test]
+    fn batched_requests() {
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        let first = get_family_message("nlctrl");
+        let second = get_family_message("no-such-family");
+        let results = socket.transact_batch(&[&first, &second, &first]).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().len(), 1);
+        let err = results[1].as_ref().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        assert_eq!(results[2].as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn large_request() {
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        let mut msg = get_family_message("nlctrl");
+        // Unknown attributes are ignored, make the message span many pages
+        let data = vec![0u8; 8192];
+        for _ in 0..20 {
+            msg.append_attribute(Attribute::new_bytes(100u16, &data));
+        }
+        assert!(msg.pack_size() > 20 * 8192);
+        socket.set_socket_send_buffer(msg.pack_size() + 4096).unwrap();
+        let messages = socket.transact(&msg).unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn extended_acknowledge() {
+        let mut socket = Socket::new(Protocol::Generic).unwrap();
+        let name = "x".repeat(40);
+        match Family::from_name(&mut socket, &name) {
+            Err(Error::ExtendedAck(ack)) => {
+                assert_eq!(ack.code, -libc::EINVAL);
+                assert!(ack.message.is_some());
+                assert_eq!(ack.attribute, Some(u16::from(AttributeId::FamilyName)));
+            }
+            _ => panic!("Expected extended acknowledge"),
+        }
+    }
 
     #[test]
     fn check_family_ids() {
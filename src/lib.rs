@@ -3,15 +3,24 @@
 //! Currently this crate is mostly used together with the nl80211-rs crate to
 //! explore the Linux kernel Netlink interface for 802.11 devices.
 
+// Lets derived code refer to the crate by name from within the crate
+extern crate self as netlink_rust;
+
 mod errors;
 #[macro_use]
 pub mod core;
+pub mod decode;
 pub mod generic;
 pub mod route;
 
 pub use crate::core::nested_attribute_array;
+#[cfg(feature = "tokio")]
+pub use crate::core::{AsyncSocket, MessageStream};
 pub use crate::core::{
-    Attribute, ConvertFrom, HardwareAddress, Message, MessageMode, NativePack, NativeUnpack,
-    Protocol, Socket,
+    Attribute, AttributeIter, AttributeRef, AttributeSet, BigEndian, Client, ConvertFrom,
+    Credentials, Direction, DumpIter, Filter, FilterBuilder, HardwareAddress, IpPrefix,
+    MemoryTransport, Message, MessageIter, MessageMode, MessageRef, NativePack, NativeUnpack,
+    NetlinkTransport, PcapPacket, PcapReader, PcapRecorder, Protocol, ReceiveInfo, ReceiveMode,
+    Socket, Transport,
 };
 pub use crate::errors::{Error, Result};
@@ -3,7 +3,8 @@
 use libc;
 
 use crate::core::{
-    pack_vec, Attribute, ConvertFrom, MessageFlags, NativePack, NativeUnpack, SendMessage,
+    attributes_pack_size, pack_vec, Attribute, ConvertFrom, MessageFlags, NativePack, NativeUnpack,
+    SendMessage,
 };
 use crate::errors::{NetlinkError, NetlinkErrorKind, Result};
 
@@ -101,6 +102,9 @@ impl SendMessage for Message {
     fn query_flags(&self) -> MessageFlags {
         MessageFlags::REQUEST | MessageFlags::DUMP
     }
+    fn pack_size(&self) -> usize {
+        1 + attributes_pack_size(&self.attributes)
+    }
 }
 
 /// Interface information message
@@ -151,7 +155,9 @@ impl InterfaceInformationMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::{Protocol, Socket};
+    use crate::core::{
+        data_message, done_message, Header, MemoryTransport, Protocol, ReceiveMode, Socket,
+    };
 
     #[test]
     fn route_get_link() {
@@ -162,4 +168,86 @@ mod tests {
             assert_eq!(m.header.identifier, FamilyId::NewLink);
         }
     }
+
+    #[test]
+    fn route_get_link_blocking() {
+        let mut socket =
+            Socket::new_with_mode(Protocol::Route, &[], ReceiveMode::Blocking).unwrap();
+        let msg = Message::new(FamilyId::GetLink);
+        socket.send_message(&msg).unwrap();
+        let messages = socket.receive_messages_until_done().unwrap();
+        assert!(!messages.is_empty());
+        for m in messages {
+            assert_eq!(m.header.identifier, FamilyId::NewLink);
+        }
+    }
+
+    #[test]
+    fn route_get_link_dump() {
+        let mut socket = Socket::new(Protocol::Route).unwrap();
+        let msg = Message::new(FamilyId::GetLink);
+        let count = socket.transact(&msg).unwrap().len();
+        assert!(count > 0);
+        let mut dumped = 0;
+        for m in socket.dump(&msg).unwrap() {
+            assert_eq!(m.unwrap().header.identifier, FamilyId::NewLink);
+            dumped += 1;
+        }
+        assert_eq!(dumped, count);
+        // Cancel after the first message, the next dump must not be busy
+        let mut iter = socket.dump(&msg).unwrap();
+        assert!(iter.next().unwrap().is_ok());
+        drop(iter);
+        assert_eq!(socket.transact(&msg).unwrap().len(), count);
+    }
+
+    #[test]
+    fn route_get_link_fake_kernel() {
+        let transport = MemoryTransport::new();
+        transport.set_handler(|request| {
+            let header = Header::unpack(request).unwrap();
+            let mut link = vec![0u8; 16];
+            link[4..8].copy_from_slice(&1i32.to_ne_bytes());
+            let flags = MessageFlags::MULTIPART;
+            let new_link = FamilyId::NewLink.into();
+            let mut datagram = data_message(&header, new_link, flags, &link);
+            link[4..8].copy_from_slice(&2i32.to_ne_bytes());
+            datagram.extend(data_message(&header, new_link, flags, &link));
+            vec![datagram, done_message(&header)]
+        });
+        let mut socket = Socket::with_transport(transport.clone(), 100);
+        let mut msg = Message::new(FamilyId::GetLink);
+        msg.attributes.push(Attribute::new_string(
+            AddressFamilyAttribute::InterfaceName,
+            "lo",
+        ));
+        let messages = socket.transact(&msg).unwrap();
+        let indices: Vec<i32> = messages
+            .iter()
+            .map(|m| {
+                InterfaceInformationMessage::unpack(&m.data)
+                    .unwrap()
+                    .1
+                    .index
+            })
+            .collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert_eq!(transport.pending(), 0);
+
+        let mut data = vec![0u8; msg.pack_size()];
+        let size = msg.pack(&mut data).unwrap();
+        let header = Header {
+            length: (Header::HEADER_SIZE + size) as u32,
+            identifier: FamilyId::GetLink.into(),
+            flags: msg.query_flags().bits(),
+            sequence: 1,
+            pid: 100,
+        };
+        let mut expected = vec![0u8; Header::HEADER_SIZE];
+        header.pack(&mut expected).unwrap();
+        expected.extend_from_slice(&data[..size]);
+        // The datagram is padded to the netlink alignment
+        expected.resize((expected.len() + 3) & !3, 0);
+        assert_eq!(transport.sent(), vec![expected]);
+    }
 }
@@ -0,0 +1,118 @@
+#![cfg(feature = "derive")]
+
+use netlink_rust::route::{AddressFamilyAttribute, InterfaceInformationMessage};
+use netlink_rust::{Attribute, Error, NativePack, NativeUnpack};
+
+#[derive(NativePack, NativeUnpack)]
+struct LinkInfo {
+    #[nla(id = 1, string)]
+    kind: String,
+    #[nla(id = 5, flag)]
+    slave: bool,
+}
+
+#[derive(NativePack, NativeUnpack)]
+struct Link {
+    family: u8,
+    kind: u16,
+    index: i32,
+    flags: u32,
+    change: u32,
+    #[nla(id = AddressFamilyAttribute::InterfaceName, string)]
+    name: String,
+    #[nla(id = AddressFamilyAttribute::MTU)]
+    mtu: Option<u32>,
+    #[nla(id = AddressFamilyAttribute::Master)]
+    master: Option<u32>,
+    #[nla(id = 18, nested)]
+    info: Option<LinkInfo>,
+}
+
+fn pack<T: NativePack>(value: &T) -> Vec<u8> {
+    let mut data = vec![0u8; value.pack_size()];
+    value.pack_unchecked(&mut data);
+    data
+}
+
+fn link(info: Option<LinkInfo>) -> Link {
+    Link {
+        family: 0,
+        kind: 1,
+        index: 2,
+        flags: 0x1043,
+        change: 0,
+        name: String::from("veth0"),
+        mtu: Some(1500),
+        master: None,
+        info,
+    }
+}
+
+#[test]
+fn interface_information() {
+    let data = pack(&link(Some(LinkInfo {
+        kind: String::from("veth"),
+        slave: true,
+    })));
+    assert_eq!(data.len(), 16 + 12 + 8 + 20);
+
+    // The derived layout matches the hand-written message
+    let (used, message) = InterfaceInformationMessage::unpack(&data).unwrap();
+    assert_eq!(used, data.len());
+    assert_eq!((message.kind, message.index, message.flags), (1, 2, 0x1043));
+    assert_eq!(message.attributes.len(), 3);
+    assert_eq!(message.attributes[0].as_string().unwrap(), "veth0");
+    assert_eq!(message.attributes[1].as_u32().unwrap(), 1500);
+    assert!(message.attributes[2].is_nested());
+    assert_eq!(message.attributes[2].attribute_type(), 18);
+
+    let (used, unpacked) = Link::unpack_with_size(&data).unwrap();
+    assert_eq!(used, data.len());
+    assert_eq!((unpacked.kind, unpacked.index), (1, 2));
+    assert_eq!(unpacked.name, "veth0");
+    assert_eq!(unpacked.mtu, Some(1500));
+    assert_eq!(unpacked.master, None);
+    let info = unpacked.info.unwrap();
+    assert_eq!(info.kind, "veth");
+    assert!(info.slave);
+}
+
+#[test]
+fn missing_attributes() {
+    let data = pack(&link(None));
+    match Link::unpack(&data[..16]) {
+        Err(Error::MissingAttribute { identifier, name }) => {
+            assert_eq!(identifier, 3);
+            assert_eq!(name, "name");
+        }
+        _ => panic!("expected a missing attribute error"),
+    }
+    // Optional attributes may be missing
+    let unpacked = Link::unpack(&data).unwrap();
+    assert!(unpacked.info.is_none());
+    // The string of a nest is required as well
+    match LinkInfo::unpack(&[]) {
+        Err(Error::MissingAttribute { identifier, .. }) => assert_eq!(identifier, 1),
+        _ => panic!("expected a missing attribute error"),
+    }
+}
+
+#[test]
+fn flag_and_string_attributes() {
+    let info = LinkInfo {
+        kind: String::from("bridge"),
+        slave: false,
+    };
+    // A cleared flag is left out, the string is nul terminated
+    let data = pack(&info);
+    let attributes: Vec<_> = netlink_rust::AttributeIter::new(&data).collect();
+    assert_eq!(attributes.len(), 1);
+    assert_eq!(attributes[0].as_bytes(), b"bridge\0");
+
+    // A set flag is an empty attribute, a string need not be nul terminated
+    let mut data = pack(&Attribute::new_bytes(1u16, b"veth"));
+    data.extend(pack(&Attribute::new_bytes(5u16, &[])));
+    let unpacked = LinkInfo::unpack(&data).unwrap();
+    assert_eq!(unpacked.kind, "veth");
+    assert!(unpacked.slave);
+}